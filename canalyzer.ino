@@ -4,9 +4,38 @@
 #include <mcp2515.h>
 #include <mcp2515_defs.h>
 
+// Frames to send arrive in the same format they are reported in:
+// `ID: <id> Data: <hex>` or `ID: <id> RTR: `, one per line.
+char line[40];
+byte lineLength = 0;
+
+void transmit(char *line)
+{
+  tCAN message;
+  char *rest;
+  if (strncmp(line, "ID: ", 4) != 0)
+    return;
+  message.id = strtoul(line + 4, &rest, 16);
+  message.header.rtr = strncmp(rest, " RTR:", 5) == 0;
+  message.header.length = 0;
+  if (!message.header.rtr)
+  {
+    if (strncmp(rest, " Data: ", 7) != 0)
+      return;
+    rest += 7;
+    while (isxdigit(rest[0]) && isxdigit(rest[1]) && message.header.length < 8)
+    {
+      char pair[3] = {rest[0], rest[1], 0};
+      message.data[message.header.length++] = strtoul(pair, NULL, 16);
+      rest += 2;
+    }
+  }
+  mcp2515_send_message(&message);
+}
+
 void setup()
 {
-  Serial.begin(9600); // For debug use
+  Serial.begin(115200); // For debug use
 
   if (Canbus.init(CANSPEED_500)) // Initialise MCP2515 CAN controller at the specified speed
     Serial.println("CAN Init ok");
@@ -19,25 +48,44 @@ void setup()
 
 void loop()
 {
+  while (Serial.available())
+  {
+    char c = Serial.read();
+    if (c == '\n' || c == '\r')
+    {
+      line[lineLength] = 0;
+      if (lineLength > 0)
+        transmit(line);
+      lineLength = 0;
+    }
+    else if (lineLength < sizeof(line) - 1)
+      line[lineLength++] = c;
+  }
+
   tCAN message;
   if (mcp2515_check_message())
   {
     if (mcp2515_get_message(&message))
     {
+      unsigned long received = micros();
       // Watch bus for PCM messages:
       //  MIL request - 
       //  Oil pressure warning indicator request - 
       Serial.print("ID: ");
       Serial.print(message.id, HEX);
       Serial.print(" ");
-      Serial.print("Data: ");
+      if (message.header.rtr)
+        Serial.print("RTR: ");
+      else
+        Serial.print("Data: ");
       for (int i = 0; i < message.header.length; i++)
       {
         char data[3];
         sprintf(data, "%02X", message.data[i]);
         Serial.print(data);
       }
-      Serial.println("");
+      Serial.print(" TS: ");
+      Serial.println(received);
     }
   }
 }
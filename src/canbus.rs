@@ -1,45 +1,785 @@
+use crate::serial;
+use anyhow::{bail, Error};
+use serde::{Deserialize, Serialize};
 use std::io::prelude::*;
-use std::io::BufReader;
-use std::fs::File;
+use std::io::{BufReader, ErrorKind, IsTerminal};
 use std::{
-    sync::mpsc::{channel, Receiver},
+    fmt,
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
     time::{Duration, SystemTime},
 };
 
+/// Events queued for the UI. Past that, raw lines are dropped and readers
+/// wait for the UI to catch up.
+const QUEUE: usize = 65536;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameKind {
+    Data,
+    Remote,
+    Error,
+}
+
+pub(crate) const CAN_ERR_FLAG: u32 = 0x2000_0000;
+
+#[derive(Clone)]
 pub struct Message {
     pub id: String,
+    pub is_extended: bool,
+    pub kind: FrameKind,
+    pub dlc: usize,
     pub data: String,
     pub ts: SystemTime,
+    /// The interface the frame was read on, when the input says; empty
+    /// otherwise.
+    pub source: String,
 }
 
 impl Message {
-    fn new(id: String, data: String) -> Self {
+    /// Builds a frame from a textual ID. A trailing `x` marks a 29-bit
+    /// extended ID explicitly; otherwise IDs written with eight digits (as
+    /// candump does) or whose value does not fit in 11 bits are extended.
+    /// The payload is uppercased, so adapters that mix cases still diff
+    /// byte for byte.
+    fn new(id: &str, kind: FrameKind, data: String) -> Self {
+        let (id, is_extended) = match id.strip_suffix(['x', 'X']) {
+            Some(id) => (id, true),
+            None => (
+                id,
+                strip_hex_prefix(id).len() >= 8 || parse_id(id).is_some_and(|id| id > 0x7FF),
+            ),
+        };
         Self {
-            id,
-            data,
+            id: id.to_string(),
+            is_extended,
+            kind,
+            dlc: data.len() / 2,
+            data: data.to_ascii_uppercase(),
             ts: SystemTime::now(),
+            source: String::new(),
+        }
+    }
+}
+
+/// Writes the frame as [`parse_frame`] reads it back.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            FrameKind::Remote if self.dlc > 0 => write!(f, "{}#R{}", self.id, self.dlc),
+            FrameKind::Remote => write!(f, "{}#R", self.id),
+            FrameKind::Data | FrameKind::Error => write!(f, "{}#{}", self.id, self.data),
+        }
+    }
+}
+
+/// Parses a hex ID written with or without a `0x` prefix.
+pub fn parse_id(id: &str) -> Option<u32> {
+    u32::from_str_radix(strip_hex_prefix(id), 16).ok()
+}
+
+/// The form IDs are keyed by, so `07e8` and `0x7E8` are the same ID: upper
+/// case hex without leading zeros, or the ID upper-cased if it isn't hex.
+pub fn key_id(id: &str) -> String {
+    match parse_id(id) {
+        Some(id) => format!("{id:X}"),
+        None => id.to_ascii_uppercase(),
+    }
+}
+
+/// An inclusive range of numeric IDs, written `<lo>..<hi>` in hex.
+#[derive(Clone, Copy)]
+pub struct IdRange {
+    lo: u32,
+    hi: u32,
+}
+
+impl IdRange {
+    pub fn contains(&self, id: u32) -> bool {
+        (self.lo..=self.hi).contains(&id)
+    }
+}
+
+impl FromStr for IdRange {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((lo, hi)) = s.split_once("..") else {
+            bail!("expected <lo>..<hi>, got `{s}`");
+        };
+        let (Some(lo), Some(hi)) = (parse_id(lo.trim()), parse_id(hi.trim())) else {
+            bail!("invalid ID in `{s}`");
+        };
+        if lo > hi {
+            bail!("`{s}` is empty");
+        }
+        Ok(Self { lo, hi })
+    }
+}
+
+impl fmt::Display for IdRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:X}..{:X}", self.lo, self.hi)
+    }
+}
+
+/// IDs matched digit by digit, written with `x` or `?` for any hex digit
+/// and an optional trailing `*` for any further digits, e.g. `7xx` or
+/// `18DA*`; or matched by bit mask as `<id>/<mask>`, e.g. `700/F00`.
+#[derive(Clone)]
+pub enum IdPattern {
+    Glob(String),
+    Mask { id: u32, mask: u32 },
+}
+
+impl IdPattern {
+    pub fn matches(&self, id: &str) -> bool {
+        match self {
+            Self::Glob(glob) => {
+                let id = strip_hex_prefix(id);
+                let (digits, open) = match glob.strip_suffix('*') {
+                    Some(digits) => (digits, true),
+                    None => (glob.as_str(), false),
+                };
+                (id.len() == digits.len() || open && id.len() > digits.len())
+                    && digits
+                        .chars()
+                        .zip(id.chars())
+                        .all(|(p, c)| p == 'x' || p.eq_ignore_ascii_case(&c))
+            }
+            Self::Mask { id: want, mask } => {
+                parse_id(id).is_some_and(|id| id & mask == want & mask)
+            }
+        }
+    }
+}
+
+impl FromStr for IdPattern {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((id, mask)) = s.split_once('/') {
+            let (Some(id), Some(mask)) = (parse_id(id.trim()), parse_id(mask.trim())) else {
+                bail!("expected <id>/<mask> in hex, got `{s}`");
+            };
+            return Ok(Self::Mask { id, mask });
+        }
+        let glob = strip_hex_prefix(s.trim()).replace(['X', '?'], "x");
+        let digits = glob.strip_suffix('*').unwrap_or(&glob);
+        if digits.is_empty() || !digits.chars().all(|c| c == 'x' || c.is_ascii_hexdigit()) {
+            bail!("expected hex digits with x, ? or a trailing *, got `{s}`");
+        }
+        Ok(Self::Glob(glob.to_ascii_uppercase().replace('X', "x")))
+    }
+}
+
+impl fmt::Display for IdPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Glob(glob) => f.write_str(glob),
+            Self::Mask { id, mask } => write!(f, "{id:X}/{mask:X}"),
         }
     }
 }
 
-pub fn recv() -> Receiver<Message> {
-    let (tx, rx) = channel();
-    std::thread::spawn(move || {
-        let serial = File::open("/dev/ttyACM0").unwrap();
-        let lines = BufReader::new(serial).lines().map(|l| l.unwrap_or_default());
-        for line in lines {
-            if line == "sleep" {
-                std::thread::sleep(Duration::from_secs(1));
+/// Whether `data` is whole hex byte pairs, which the rest of the UI relies
+/// on. An empty payload is fine.
+fn is_hex_payload(data: &str) -> bool {
+    data.len().is_multiple_of(2) && data.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn strip_hex_prefix(id: &str) -> &str {
+    id.strip_prefix("0x")
+        .or_else(|| id.strip_prefix("0X"))
+        .unwrap_or(id)
+}
+
+/// The text format frames are read in from a serial device or stdin.
+#[derive(Clone, Copy, Default)]
+pub enum Format {
+    /// `ID: <id> Data: <hex>` lines from the bundled sketch.
+    #[default]
+    Canalyzer,
+    /// Lawicel slcan frames, as sent by CANable and similar adapters.
+    Slcan,
+    /// `candump -L` lines, e.g. piped in from can-utils.
+    Candump,
+}
+
+/// Parses one line into a frame and, if the line carries one, a timestamp
+/// in microseconds on the sender's clock.
+type Parser = fn(&str) -> Option<(Message, Option<u64>)>;
+
+impl Format {
+    fn parser(self) -> Parser {
+        match self {
+            Self::Canalyzer => parse_canalyzer,
+            Self::Slcan => parse_slcan,
+            Self::Candump => {
+                |line| parse_candump(line).map(|(ts, msg)| (msg, Some((ts * 1e6) as u64)))
             }
-            let mut words = line.split(' ');
-            if words.next() == Some("ID:") {
-                if let Some(id) = words.next() {
-                    let data = words.nth(1).unwrap_or_default();
-                    tx.send(Message::new(id.to_string(), data.to_string()))
-                        .unwrap();
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "canalyzer" => Ok(Self::Canalyzer),
+            "slcan" => Ok(Self::Slcan),
+            "candump" => Ok(Self::Candump),
+            _ => bail!("invalid format `{s}` (expected canalyzer, slcan or candump)"),
+        }
+    }
+}
+
+pub enum Event {
+    /// A line as read from the device, before parsing, without its line
+    /// ending.
+    Raw(Vec<u8>),
+    Frame(Message),
+    /// An input line that isn't a frame, as received.
+    Malformed(String),
+    Error(String),
+    Connection(Connection),
+}
+
+#[derive(Clone, Copy)]
+pub enum Connection {
+    Connected,
+    Reconnecting,
+}
+
+/// A transport that frames can be read from. Each source owns its reader
+/// thread; the returned receiver stops yielding once the source is exhausted.
+pub trait Source {
+    fn messages(self: Box<Self>) -> Receiver<Event>;
+
+    /// A way to send frames onto the bus, for sources attached to one.
+    fn transmitter(&self) -> Option<Box<dyn Transmit>> {
+        None
+    }
+}
+
+/// Writes frames onto the bus.
+pub trait Transmit: Send {
+    fn transmit(&mut self, frame: &Message) -> std::io::Result<()>;
+}
+
+/// Reads frames in `format` from a serial device. If the device disappears
+/// it is reopened every `reconnect_interval` until it comes back; plain
+/// files are read once. slcan adapters are set to `bitrate` and their
+/// channel opened on every connect.
+pub struct SerialSource {
+    pub path: PathBuf,
+    pub settings: serial::Settings,
+    pub format: Format,
+    pub bitrate: u32,
+    pub reconnect_interval: Duration,
+}
+
+impl Source for SerialSource {
+    fn messages(self: Box<Self>) -> Receiver<Event> {
+        spawn(move |tx| loop {
+            match serial::open(&self.path, &self.settings) {
+                Ok(serial) => {
+                    let is_device = serial.is_terminal();
+                    if is_device {
+                        if let Format::Slcan = self.format {
+                            if let Err(e) = open_slcan(&self.path, self.bitrate) {
+                                fail(&tx, format!("cannot open slcan channel: {e}"));
+                            }
+                        }
+                        let _ = tx.send(Event::Connection(Connection::Connected));
+                    }
+                    if let Err(e) = read(BufReader::new(serial), &tx, self.format.parser()) {
+                        fail(&tx, format!("read failed: {e}"));
+                    }
+                    if !is_device {
+                        return;
+                    }
                 }
+                Err(e) => fail(&tx, format!("cannot open {}: {e}", self.path.display())),
+            }
+            // Nobody is listening any more, e.g. the UI has quit.
+            if tx
+                .send(Event::Connection(Connection::Reconnecting))
+                .is_err()
+            {
+                return;
             }
+            std::thread::sleep(self.reconnect_interval);
+        })
+    }
+
+    fn transmitter(&self) -> Option<Box<dyn Transmit>> {
+        match self.format {
+            Format::Canalyzer | Format::Slcan => Some(Box::new(SerialTransmit {
+                path: self.path.clone(),
+                format: self.format,
+                port: None,
+            })),
+            Format::Candump => None,
         }
-    });
+    }
+}
+
+/// Sends frames to a serial adapter in its own format. The device is
+/// opened for the first frame and kept open, since opening it can reset
+/// boards that reboot on DTR. It is reopened after a failed write, so
+/// sending keeps working across reconnects.
+struct SerialTransmit {
+    path: PathBuf,
+    format: Format,
+    port: Option<File>,
+}
+
+impl Transmit for SerialTransmit {
+    fn transmit(&mut self, frame: &Message) -> std::io::Result<()> {
+        let port = match &mut self.port {
+            Some(port) => port,
+            None => {
+                let port = OpenOptions::new().write(true).open(&self.path)?;
+                if !port.is_terminal() {
+                    return Err(std::io::Error::other(format!(
+                        "{} is not a serial device",
+                        self.path.display()
+                    )));
+                }
+                self.port.insert(port)
+            }
+        };
+        let id = parse_id(&frame.id).unwrap_or_default();
+        let data = frame.data.to_ascii_uppercase();
+        let line = match (self.format, frame.kind) {
+            (Format::Slcan, kind) => {
+                let (tag, width) = match (kind, frame.is_extended) {
+                    (FrameKind::Remote, false) => ('r', 3),
+                    (FrameKind::Remote, true) => ('R', 8),
+                    (_, false) => ('t', 3),
+                    (_, true) => ('T', 8),
+                };
+                format!("{tag}{id:0width$X}{}{data}\r", frame.dlc)
+            }
+            (_, FrameKind::Remote) => format!("ID: {id:X} RTR: \n"),
+            (_, _) => format!("ID: {id:X} Data: {data}\n"),
+        };
+        let written = port.write_all(line.as_bytes());
+        if written.is_err() {
+            self.port = None;
+        }
+        written
+    }
+}
+
+pub struct StdinSource {
+    pub format: Format,
+}
+
+impl Source for StdinSource {
+    fn messages(self: Box<Self>) -> Receiver<Event> {
+        spawn(move |tx| {
+            if let Err(e) = read(std::io::stdin().lock(), &tx, self.format.parser()) {
+                fail(&tx, format!("read failed: {e}"));
+            }
+        })
+    }
+}
+
+/// Replays a `candump -L` log, sleeping between frames according to the
+/// recorded timestamps divided by `speed`. A `speed` of 0 replays as fast as
+/// possible. Frames are stamped with the time they are replayed so the age
+/// coloring follows the original cadence.
+pub struct ReplaySource {
+    pub path: PathBuf,
+    pub speed: f64,
+}
+
+impl Source for ReplaySource {
+    fn messages(self: Box<Self>) -> Receiver<Event> {
+        spawn(move |tx| {
+            let log = match File::open(&self.path) {
+                Ok(log) => log,
+                Err(e) => return fail(&tx, format!("cannot open {}: {e}", self.path.display())),
+            };
+            let mut prev = None;
+            for line in BufReader::new(log).lines().map_while(Result::ok) {
+                let Some((ts, mut msg)) = parse_candump(&line) else {
+                    // Comments are skipped; the timestamps already pace
+                    // the replay, so `#sleep` has no meaning here.
+                    if !line.trim().is_empty() && !line.starts_with('#') {
+                        let _ = tx.send(Event::Malformed(line));
+                    }
+                    continue;
+                };
+                if let Some(prev) = prev.filter(|&prev| self.speed > 0.0 && ts > prev) {
+                    std::thread::sleep(Duration::from_secs_f64((ts - prev) / self.speed));
+                }
+                prev = Some(ts);
+                msg.ts = SystemTime::now();
+                if tx.send(Event::Frame(msg)).is_err() {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+/// Hands over a fixed list of events, as if a device had sent them, so the
+/// pipeline can be tested without one.
+#[cfg(test)]
+pub struct MockSource(pub Vec<Event>);
+
+#[cfg(test)]
+impl Source for MockSource {
+    fn messages(self: Box<Self>) -> Receiver<Event> {
+        spawn(move |tx| {
+            for event in self.0 {
+                if tx.send(event).is_err() {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+/// The slcan `S<n>` code for a bitrate, if the protocol has one.
+pub fn slcan_bitrate(bitrate: u32) -> Option<u8> {
+    let code = [
+        10_000, 20_000, 50_000, 100_000, 125_000, 250_000, 500_000, 800_000, 1_000_000,
+    ]
+    .iter()
+    .position(|&rate| rate == bitrate)?;
+    Some(code as u8)
+}
+
+/// Sets the adapter's bitrate and opens its channel. It is closed first in
+/// case a previous session left it open, which makes `S` fail.
+fn open_slcan(path: &Path, bitrate: u32) -> std::io::Result<()> {
+    let code = slcan_bitrate(bitrate).unwrap_or(6);
+    let mut port = OpenOptions::new().write(true).open(path)?;
+    port.write_all(format!("C\rS{code}\rO\r").as_bytes())
+}
+
+pub(crate) fn spawn(f: impl FnOnce(SyncSender<Event>) + Send + 'static) -> Receiver<Event> {
+    let (tx, rx) = sync_channel(QUEUE);
+    std::thread::spawn(move || f(tx));
     rx
 }
+
+pub(crate) fn fail(tx: &SyncSender<Event>, error: String) {
+    let _ = tx.send(Event::Error(error));
+}
+
+/// Maps the adapter's microsecond counter onto wall-clock time. The first
+/// stamped frame anchors the counter to now, and it is anchored again
+/// whenever the counter goes backwards (it wrapped or the adapter reset).
+/// Stamps are never allowed to run ahead of the host clock; the anchor is
+/// pulled back instead, which absorbs a fast adapter clock.
+#[derive(Default)]
+struct DeviceClock {
+    anchor: Option<(u64, SystemTime)>,
+}
+
+impl DeviceClock {
+    fn stamp(&mut self, micros: u64) -> SystemTime {
+        let now = SystemTime::now();
+        match self.anchor {
+            Some((start, at)) if micros >= start => {
+                let ts = at + Duration::from_micros(micros - start);
+                match ts.duration_since(now) {
+                    Ok(ahead) => {
+                        self.anchor = Some((start, at - ahead));
+                        now
+                    }
+                    Err(_) => ts,
+                }
+            }
+            _ => {
+                self.anchor = Some((micros, now));
+                now
+            }
+        }
+    }
+}
+
+/// Reads frames line by line with `parse`. Lines that aren't frames,
+/// including ones garbled into invalid UTF-8 by a wrong baud rate, are
+/// passed on as [`Event::Malformed`]; blank lines are skipped. Raw lines
+/// are dropped while the UI is behind.
+///
+/// Lines starting with `#` are comments, except for the control line
+/// `#sleep <ms>`, which pauses reading so that test input can pace itself.
+fn read(mut reader: impl BufRead, tx: &SyncSender<Event>, parse: Parser) -> std::io::Result<()> {
+    let mut clock = DeviceClock::default();
+    loop {
+        let mut line = Vec::new();
+        if read_line(&mut reader, &mut line)? == 0 {
+            break;
+        }
+        if let Err(TrySendError::Disconnected(_)) = tx.try_send(Event::Raw(line.clone())) {
+            break;
+        }
+        let line = match String::from_utf8(line) {
+            Ok(line) => line,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        };
+        let line = line.trim_end();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(ms) = comment.strip_prefix("sleep ") {
+                match ms.trim().parse() {
+                    Ok(ms) => std::thread::sleep(Duration::from_millis(ms)),
+                    Err(_) => {
+                        if tx.send(Event::Malformed(line.to_string())).is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+            continue;
+        }
+        match parse(line) {
+            Some((mut msg, micros)) => {
+                if let Some(micros) = micros {
+                    msg.ts = clock.stamp(micros);
+                }
+                if tx.send(Event::Frame(msg)).is_err() {
+                    break;
+                }
+            }
+            None if line.is_empty() => {}
+            None => {
+                if tx.send(Event::Malformed(line.to_string())).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Like `read_until(b'\n')`, but a bare `\r` also ends the line, since
+/// slcan adapters end frames with one.
+fn read_line(reader: &mut impl BufRead, line: &mut Vec<u8>) -> std::io::Result<usize> {
+    let mut total = 0;
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            return Ok(total);
+        }
+        match available.iter().position(|&b| b == b'\n' || b == b'\r') {
+            Some(end) => {
+                line.extend_from_slice(&available[..end]);
+                reader.consume(end + 1);
+                return Ok(total + end + 1);
+            }
+            None => {
+                let len = available.len();
+                line.extend_from_slice(available);
+                reader.consume(len);
+                total += len;
+            }
+        }
+    }
+}
+
+/// Parses an `ID: <id> Data: <hex>` line, with `RTR:` or `Error:` in place
+/// of `Data:` for other frame kinds and an optional trailing `TS: <micros>`
+/// device timestamp. Payloads that aren't whole hex bytes are rejected.
+fn parse_canalyzer(line: &str) -> Option<(Message, Option<u64>)> {
+    let mut words = line.split(' ');
+    if words.next()? != "ID:" {
+        return None;
+    }
+    let id = words.next().filter(|id| !id.is_empty())?;
+    let kind = match words.next()? {
+        "Data:" => FrameKind::Data,
+        "RTR:" => FrameKind::Remote,
+        "Error:" => FrameKind::Error,
+        _ => return None,
+    };
+    let data = words.next().unwrap_or_default();
+    if !is_hex_payload(data) {
+        return None;
+    }
+    let micros = match (words.next(), words.next()) {
+        (Some("TS:"), Some(micros)) => Some(micros.parse().ok()?),
+        (None, _) => None,
+        _ => return None,
+    };
+    Some((Message::new(id, kind, data.to_string()), micros))
+}
+
+/// Parses an slcan frame: `t<iii><len><data>` for standard and
+/// `T<iiiiiiii><len><data>` for extended IDs, or `r` and `R` for remote
+/// frames without data. Adapters with timestamps enabled append the
+/// milliseconds within the minute as four hex digits.
+fn parse_slcan(line: &str) -> Option<(Message, Option<u64>)> {
+    let (kind, id_len) = match line.get(..1)? {
+        "t" => (FrameKind::Data, 3),
+        "T" => (FrameKind::Data, 8),
+        "r" => (FrameKind::Remote, 3),
+        "R" => (FrameKind::Remote, 8),
+        _ => return None,
+    };
+    let id = line.get(1..1 + id_len)?;
+    parse_id(id)?;
+    let dlc = line.get(1 + id_len..2 + id_len)?.parse::<usize>().ok()?;
+    let rest = &line[2 + id_len..];
+    let data_len = match kind {
+        FrameKind::Remote => 0,
+        FrameKind::Data | FrameKind::Error => 2 * dlc,
+    };
+    let data = rest.get(..data_len)?;
+    if !is_hex_payload(data) {
+        return None;
+    }
+    let millis = match &rest[data_len..] {
+        "" => None,
+        ts if ts.len() == 4 => Some(u64::from_str_radix(ts, 16).ok()?),
+        _ => return None,
+    };
+    let msg = Message {
+        dlc,
+        ..Message::new(id, kind, data.to_string())
+    };
+    Some((msg, millis.map(|millis| millis * 1000)))
+}
+
+/// Parses a classic CAN frame typed as `<id>#<data>`, or `<id>#R<len>` for
+/// a remote frame, as cansend takes them.
+pub fn parse_frame(frame: &str) -> Option<Message> {
+    let (id, data) = frame.trim().split_once('#')?;
+    parse_id(id)?;
+    let msg = match data.strip_prefix(['R', 'r']) {
+        Some("") => Message::new(id, FrameKind::Remote, String::new()),
+        Some(dlc) => Message {
+            dlc: dlc.parse().ok()?,
+            ..Message::new(id, FrameKind::Remote, String::new())
+        },
+        None if is_hex_payload(data) => Message::new(id, FrameKind::Data, data.to_string()),
+        None => return None,
+    };
+    (msg.dlc <= 8).then_some(msg)
+}
+
+/// Parses a `candump -L` line. Remote frames are written as `<id>#R` with
+/// an optional length, and error frames carry `CAN_ERR_FLAG` in their ID.
+fn parse_candump(line: &str) -> Option<(f64, Message)> {
+    let mut words = line.split_whitespace();
+    let ts = words
+        .next()?
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .parse()
+        .ok()?;
+    let interface = words.next()?;
+    let (id, data) = words.next()?.split_once('#')?;
+    let mut msg = if let Some(dlc) = data.strip_prefix('R') {
+        Message {
+            dlc: dlc.parse().unwrap_or_default(),
+            ..Message::new(id, FrameKind::Remote, String::new())
+        }
+    } else if !is_hex_payload(data) {
+        return None;
+    } else if id.len() == 8 && parse_id(id).is_some_and(|id| id & CAN_ERR_FLAG != 0) {
+        Message::new(id, FrameKind::Error, data.to_string())
+    } else {
+        Message::new(id, FrameKind::Data, data.to_string())
+    };
+    msg.source = interface.to_string();
+    Some((ts, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(msg: &Message) -> crate::RowKey {
+        crate::row_key(
+            &msg.source,
+            &msg.id,
+            msg.is_extended,
+            msg.kind == FrameKind::Error,
+        )
+    }
+
+    #[test]
+    fn mock_source_yields_its_events_in_order() {
+        let frames = ["123#01", "456#R", "123#02"].map(|frame| parse_frame(frame).unwrap());
+        let mut events: Vec<_> = frames.iter().cloned().map(Event::Frame).collect();
+        events.insert(1, Event::Malformed("garbage".to_string()));
+        let source: Box<dyn Source> = Box::new(MockSource(events));
+        assert!(source.transmitter().is_none());
+
+        let received: Vec<_> = source
+            .messages()
+            .iter()
+            .map(|event| match event {
+                Event::Frame(frame) => format!("{}#{}", frame.id, frame.data),
+                Event::Malformed(line) => format!("malformed {line}"),
+                _ => panic!("unexpected event"),
+            })
+            .collect();
+        assert_eq!(received, ["123#01", "malformed garbage", "456#", "123#02"]);
+    }
+
+    #[test]
+    fn hex_payloads() {
+        assert!(is_hex_payload(""));
+        assert!(is_hex_payload("00FF"));
+        assert!(is_hex_payload("deadbeef"));
+        assert!(is_hex_payload("DeAdBeEf"));
+        assert!(!is_hex_payload("0"));
+        assert!(!is_hex_payload("ABC"));
+        assert!(!is_hex_payload("0G"));
+        assert!(!is_hex_payload("0x12"));
+        assert!(!is_hex_payload("12 34"));
+        assert!(!is_hex_payload("éé"));
+    }
+
+    #[test]
+    fn parsers_reject_malformed_payloads() {
+        assert!(parse_canalyzer("ID: 123 Data: ABC").is_none());
+        assert!(parse_canalyzer("ID: 123 Data: ZZ").is_none());
+        assert!(parse_frame("123#0").is_none());
+        assert!(parse_candump("(1.000000) can0 123#XY").is_none());
+        let (empty, _) = parse_canalyzer("ID: 123 Data: ").unwrap();
+        assert_eq!((empty.data.as_str(), empty.dlc), ("", 0));
+    }
+
+    #[test]
+    fn mixed_case_ids_share_a_row() {
+        let (lower, _) = parse_canalyzer("ID: 0a1 Data: aabb").unwrap();
+        let (upper, _) = parse_canalyzer("ID: 0A1 Data: AABB").unwrap();
+        assert_eq!(key(&lower), key(&upper));
+        assert_eq!(lower.data, upper.data);
+
+        let (lower, _) = parse_slcan("t0a12aabb").unwrap();
+        let (upper, _) = parse_slcan("t0A12AABB").unwrap();
+        assert_eq!(key(&lower), key(&upper));
+        assert_eq!(lower.data, "AABB");
+
+        let (_, lower) = parse_candump("(1.000000) can0 1abcdef0#aabb").unwrap();
+        let (_, upper) = parse_candump("(1.000000) can0 1ABCDEF0#AABB").unwrap();
+        assert_eq!(key(&lower), key(&upper));
+        assert_eq!(lower.data, upper.data);
+    }
+
+    #[test]
+    fn other_ids_and_extended_ids_get_their_own_rows() {
+        let (standard, _) = parse_canalyzer("ID: 0a1 Data: 00").unwrap();
+        let (extended, _) = parse_canalyzer("ID: 000000a1 Data: 00").unwrap();
+        let (other, _) = parse_canalyzer("ID: 0a2 Data: 00").unwrap();
+        assert_ne!(key(&standard), key(&extended));
+        assert_ne!(key(&standard), key(&other));
+    }
+}
@@ -0,0 +1,80 @@
+//! Guesses whether the last payload byte is a checksum over the bytes
+//! before it, by trying common schemes against an ID's history.
+
+struct Scheme {
+    name: &'static str,
+    compute: fn(&[u8]) -> u8,
+}
+
+const SCHEMES: &[Scheme] = &[
+    Scheme {
+        name: "sum",
+        compute: |data| data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)),
+    },
+    Scheme {
+        name: "negated sum",
+        compute: |data| {
+            data.iter()
+                .fold(0u8, |sum, &b| sum.wrapping_add(b))
+                .wrapping_neg()
+        },
+    },
+    Scheme {
+        name: "xor",
+        compute: |data| data.iter().fold(0, |x, &b| x ^ b),
+    },
+    Scheme {
+        name: "crc8",
+        compute: |data| crc8(data, 0x07, 0x00, 0x00),
+    },
+    Scheme {
+        name: "crc8 sae-j1850",
+        compute: |data| crc8(data, 0x1D, 0xFF, 0xFF),
+    },
+    Scheme {
+        name: "crc8 autosar",
+        compute: |data| crc8(data, 0x2F, 0xFF, 0xFF),
+    },
+];
+
+/// MSB-first CRC-8 with the given polynomial, initial value and final XOR.
+fn crc8(data: &[u8], poly: u8, init: u8, xor_out: u8) -> u8 {
+    let mut crc = init;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ poly
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc ^ xor_out
+}
+
+/// The schemes that predict the last byte of at least one payload, with the
+/// share of payloads they predict, best first. Payloads shorter than two
+/// bytes have nothing to check and are skipped.
+pub fn rank(payloads: &[Vec<u8>]) -> Vec<(&'static str, f64)> {
+    let checked: Vec<_> = payloads.iter().filter(|data| data.len() >= 2).collect();
+    if checked.is_empty() {
+        return Vec::new();
+    }
+    let mut ranked: Vec<_> = SCHEMES
+        .iter()
+        .map(|scheme| {
+            let hits = checked
+                .iter()
+                .filter(|data| {
+                    let (last, rest) = data.split_last().unwrap();
+                    (scheme.compute)(rest) == *last
+                })
+                .count();
+            (scheme.name, hits as f64 / checked.len() as f64)
+        })
+        .filter(|&(_, rate)| rate > 0.0)
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked
+}
@@ -0,0 +1,433 @@
+use crate::{
+    canbus,
+    config::{self, Config},
+    mqtt,
+    regex::Regex,
+    serial,
+    theme::{Fade, Theme},
+    trigger::Trigger,
+};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+const USAGE: &str = "\
+usage: canalyzer [options]
+
+options:
+  --device <path>    serial device to read frames from, or `-` for stdin
+                     (default: /dev/ttyACM0)
+  --baud <rate>      serial baud rate (default: 115200, as the sketch uses)
+  --data-bits <n>    serial data bits, 5-8 (default: 8)
+  --parity <p>       serial parity: none, odd or even (default: none)
+  --stop-bits <n>    serial stop bits, 1 or 2 (default: 1)
+  --format <name>    format of the lines read from the device: canalyzer,
+                     slcan or candump (default: canalyzer)
+  --reconnect-interval <secs>
+                     delay between attempts to reopen a lost device
+                     (default: 1)
+  --bitrate <bps>    bus bitrate used to estimate the load and set on slcan
+                     adapters (default: 500000)
+  --interface <if>   read raw frames from a SocketCAN interface (Linux only);
+                     also names the interface in candump exports and logs
+  --replay <file>    replay a `candump -L` log instead of reading a device
+  --replay-speed <x> replay speed multiplier, 0 for as fast as possible
+                     (default: 1)
+  --load <file>      inspect a JSON dump written by a previous session
+                     instead of reading a device
+  --diff <a> <b>     compare the latest payloads of two JSON dumps, print
+                     the IDs and bytes that differ and exit
+  --dbc <file>       decode signals of the selected message with a DBC file
+  --rules <file>     color rows with the JSON rules in the file
+  --filter <regex>   only show IDs or latest payloads matching the regex
+  --id-range <lo>..<hi>
+                     only show IDs in the inclusive hex range, e.g.
+                     0x100..0x1FF; the others are still captured
+  --trigger <id>#<data>
+                     flash the status bar when a frame for the ID arrives
+                     whose payload starts with the data; may be repeated
+  --bell             also ring the terminal bell when a trigger fires
+  --theme <name>     color theme: dark, light, high-contrast or monochrome
+                     (default: monochrome if NO_COLOR is set or stdout
+                     isn't a terminal, else the last one used, or dark)
+  --fade <steps>     how a frame's background fades with age, as
+                     <secs>=<color> pairs, e.g. 1=#ff9b35,2=#bd370a
+                     (default: from the theme)
+  --stale-timeout <secs>
+                     badge IDs with no frame for this long as STALE, 0 to
+                     disable (default: 5)
+  --sink-stale       sort stale IDs below the live ones
+  --diff-direction   color changed bytes by whether they went up or down
+  --col-width <n>    width of the payload columns (default: fits the longest
+                     payload)
+  --max-history-cols <n>
+                     show at most this many payload columns however wide
+                     the terminal is; < and > adjust it (default: as many
+                     as fit)
+  --history <n>      frames of history kept per ID (default: 1000)
+  --poll-interval <secs>
+                     longest wait for new frames or keys between checks;
+                     lower is more responsive on busy buses (default: 0.25)
+  --rate-smoothing <secs>
+                     time constant of the moving average shown as the rate,
+                     0 for the raw count over the last second (default: 1)
+  --duration <secs>  collect for this long without the TUI, then write the
+                     session as on exit
+  --wait-for <id,...>
+                     collect without the TUI until every ID has been
+                     seen, or fail if --duration runs out first
+  --stream           print every frame as a line of JSON as it arrives
+                     instead of running the TUI
+  --output <file>    write the captured session to a file as pretty JSON
+                     instead of printing it to stdout on exit
+  --export-csv <file> write every captured frame to a CSV file on exit; x
+                     exports the current view to it on demand
+  --export-candump <file>
+                     write every captured frame to a `candump -L` log on
+                     exit; d exports the current view to it on demand
+  --export-pcap <file>
+                     write every captured frame to a pcapng file for
+                     Wireshark on exit
+  --relative-time    count timestamps in exports and the UI from the start
+                     of the capture instead of the Unix epoch
+  --log <file>       append every frame to a `candump -L` log as it arrives
+  --mqtt <broker>    publish every frame to canalyzer/<id>, or to
+                     canalyzer/<interface>/<id> for inputs that name it, on
+                     an MQTT broker given as mqtt://<host>[:<port>]
+  --metrics-addr <addr>
+                     serve per-ID frame counts and rates to Prometheus at
+                     http://<addr>/metrics, e.g. 127.0.0.1:9100
+  --ws-addr <addr>   broadcast every frame as JSON to WebSocket clients of
+                     ws://<addr>, e.g. 127.0.0.1:9101
+  --state-file <path>
+                     where ignored and pinned IDs are remembered
+                     (default: ~/.config/canalyzer/state.json)
+  --no-persist       don't load or save ignored and pinned IDs
+  --config <file>    read defaults for these options from a TOML file
+                     (default: ./canalyzer.toml, then
+                     ~/.config/canalyzer/canalyzer.toml)
+  -h, --help         print this help and exit
+
+Flags set in the config file are turned off again by --no-<flag>, e.g.
+--no-bell, or by --persist for --no-persist.";
+
+pub struct Args {
+    pub device: PathBuf,
+    pub serial: serial::Settings,
+    pub format: canbus::Format,
+    pub reconnect_interval: Duration,
+    pub bitrate: u32,
+    pub interface: Option<String>,
+    pub replay: Option<PathBuf>,
+    pub replay_speed: f64,
+    pub load: Option<PathBuf>,
+    pub diff: Option<(PathBuf, PathBuf)>,
+    pub triggers: Vec<Trigger>,
+    pub bell: bool,
+    pub theme: Option<Theme>,
+    pub fade: Option<Fade>,
+    pub stale_timeout: Duration,
+    pub sink_stale: bool,
+    pub diff_direction: bool,
+    pub history: usize,
+    pub poll_interval: Duration,
+    pub rate_smoothing: Duration,
+    pub col_width: Option<u16>,
+    pub max_history_cols: Option<usize>,
+    pub dbc: Option<PathBuf>,
+    pub rules: Option<PathBuf>,
+    pub filter: Option<Regex>,
+    pub id_range: Option<canbus::IdRange>,
+    pub duration: Option<Duration>,
+    pub wait_for: Vec<u32>,
+    pub stream: bool,
+    pub output: Option<PathBuf>,
+    pub export_csv: Option<PathBuf>,
+    pub export_candump: Option<PathBuf>,
+    pub export_pcap: Option<PathBuf>,
+    pub relative_time: bool,
+    pub log: Option<PathBuf>,
+    pub mqtt: Option<mqtt::Broker>,
+    pub metrics_addr: Option<SocketAddr>,
+    pub ws_addr: Option<SocketAddr>,
+    pub state_file: Option<PathBuf>,
+    pub no_persist: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            device: PathBuf::from("/dev/ttyACM0"),
+            serial: serial::Settings::default(),
+            format: canbus::Format::default(),
+            reconnect_interval: Duration::from_secs(1),
+            bitrate: 500_000,
+            interface: None,
+            replay: None,
+            replay_speed: 1.0,
+            load: None,
+            diff: None,
+            triggers: Vec::new(),
+            bell: false,
+            theme: None,
+            fade: None,
+            stale_timeout: Duration::from_secs(5),
+            sink_stale: false,
+            diff_direction: false,
+            history: 1000,
+            poll_interval: Duration::from_millis(250),
+            rate_smoothing: Duration::from_secs(1),
+            col_width: None,
+            max_history_cols: None,
+            dbc: None,
+            rules: None,
+            filter: None,
+            id_range: None,
+            duration: None,
+            wait_for: Vec::new(),
+            stream: false,
+            output: None,
+            export_csv: None,
+            export_candump: None,
+            export_pcap: None,
+            relative_time: false,
+            log: None,
+            mqtt: None,
+            metrics_addr: None,
+            ws_addr: None,
+            state_file: None,
+            no_persist: false,
+        }
+    }
+}
+
+impl Args {
+    /// Parses the command line over the defaults from the config file.
+    pub fn parse() -> Result<Self> {
+        let cli: Vec<String> = std::env::args().skip(1).collect();
+        let path = match cli.iter().position(|arg| arg == "--config") {
+            Some(i) => Some(value(&mut cli.iter().skip(i + 1).cloned(), "--config")?.into()),
+            None => config::default_path(),
+        };
+        let defaults = match path {
+            Some(path) => Self::parse_from(Config::load(&path)?.args(), Self::default())
+                .with_context(|| format!("invalid config file {}", path.display()))?,
+            None => Self::default(),
+        };
+        Self::parse_from(cli, defaults)?.validated()
+    }
+
+    /// Interface name written to candump exports.
+    pub fn candump_interface(&self) -> &str {
+        self.interface.as_deref().unwrap_or("can0")
+    }
+
+    /// Parses `args` over `parsed`. Options given here override those in
+    /// `parsed`, and repeatable ones replace its values rather than adding
+    /// to them.
+    fn parse_from(args: Vec<String>, mut parsed: Self) -> Result<Self> {
+        if args.iter().any(|arg| arg == "--trigger") {
+            parsed.triggers.clear();
+        }
+        if args.iter().any(|arg| arg == "--wait-for") {
+            parsed.wait_for.clear();
+        }
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // Already read by `parse`.
+                "--config" => drop(value(&mut args, &arg)?),
+                "--device" => parsed.device = value(&mut args, &arg)?.into(),
+                "--baud" => parsed.serial.baud = parse(&mut args, &arg)?,
+                "--data-bits" => parsed.serial.data_bits = parse(&mut args, &arg)?,
+                "--parity" => parsed.serial.parity = parse(&mut args, &arg)?,
+                "--stop-bits" => parsed.serial.stop_bits = parse(&mut args, &arg)?,
+                "--format" => parsed.format = parse(&mut args, &arg)?,
+                "--reconnect-interval" => {
+                    parsed.reconnect_interval = Duration::try_from_secs_f64(parse(&mut args, &arg)?)
+                        .context("invalid value for `--reconnect-interval`")?
+                }
+                "--bitrate" => parsed.bitrate = parse(&mut args, &arg)?,
+                "--interface" => parsed.interface = Some(value(&mut args, &arg)?),
+                "--replay" => parsed.replay = Some(value(&mut args, &arg)?.into()),
+                "--replay-speed" => parsed.replay_speed = parse(&mut args, &arg)?,
+                "--dbc" => parsed.dbc = Some(value(&mut args, &arg)?.into()),
+                "--rules" => parsed.rules = Some(value(&mut args, &arg)?.into()),
+                "--filter" => parsed.filter = Some(parse(&mut args, &arg)?),
+                "--id-range" => parsed.id_range = Some(parse(&mut args, &arg)?),
+                "--load" => parsed.load = Some(value(&mut args, &arg)?.into()),
+                "--diff" => {
+                    let a = value(&mut args, &arg)?.into();
+                    parsed.diff = Some((a, value(&mut args, &arg)?.into()));
+                }
+                "--trigger" => parsed.triggers.push(parse(&mut args, &arg)?),
+                "--bell" => parsed.bell = true,
+                "--no-bell" => parsed.bell = false,
+                "--theme" => parsed.theme = Some(parse(&mut args, &arg)?),
+                "--fade" => parsed.fade = Some(parse(&mut args, &arg)?),
+                "--stale-timeout" => {
+                    parsed.stale_timeout = Duration::try_from_secs_f64(parse(&mut args, &arg)?)
+                        .context("invalid value for `--stale-timeout`")?
+                }
+                "--sink-stale" => parsed.sink_stale = true,
+                "--no-sink-stale" => parsed.sink_stale = false,
+                "--diff-direction" => parsed.diff_direction = true,
+                "--no-diff-direction" => parsed.diff_direction = false,
+                "--history" => parsed.history = parse(&mut args, &arg)?,
+                "--rate-smoothing" => {
+                    parsed.rate_smoothing = Duration::try_from_secs_f64(parse(&mut args, &arg)?)
+                        .context("invalid value for `--rate-smoothing`")?
+                }
+                "--poll-interval" => {
+                    parsed.poll_interval = Duration::try_from_secs_f64(parse(&mut args, &arg)?)
+                        .context("invalid value for `--poll-interval`")?
+                }
+                "--col-width" => parsed.col_width = Some(parse(&mut args, &arg)?),
+                "--max-history-cols" => parsed.max_history_cols = Some(parse(&mut args, &arg)?),
+                "--duration" => {
+                    parsed.duration = Some(
+                        Duration::try_from_secs_f64(parse(&mut args, &arg)?)
+                            .context("invalid value for `--duration`")?,
+                    )
+                }
+                "--wait-for" => {
+                    for id in value(&mut args, &arg)?.split(',') {
+                        parsed.wait_for.push(
+                            canbus::parse_id(id.trim())
+                                .ok_or_else(|| anyhow!("invalid ID `{id}` for `--wait-for`"))?,
+                        );
+                    }
+                }
+                "--stream" => parsed.stream = true,
+                "--no-stream" => parsed.stream = false,
+                "--output" => parsed.output = Some(value(&mut args, &arg)?.into()),
+                "--export-csv" => parsed.export_csv = Some(value(&mut args, &arg)?.into()),
+                "--export-candump" => parsed.export_candump = Some(value(&mut args, &arg)?.into()),
+                "--export-pcap" => parsed.export_pcap = Some(value(&mut args, &arg)?.into()),
+                "--relative-time" => parsed.relative_time = true,
+                "--no-relative-time" => parsed.relative_time = false,
+                "--log" => parsed.log = Some(value(&mut args, &arg)?.into()),
+                "--mqtt" => parsed.mqtt = Some(parse(&mut args, &arg)?),
+                "--metrics-addr" => parsed.metrics_addr = Some(parse(&mut args, &arg)?),
+                "--ws-addr" => parsed.ws_addr = Some(parse(&mut args, &arg)?),
+                "--state-file" => parsed.state_file = Some(value(&mut args, &arg)?.into()),
+                "--no-persist" => parsed.no_persist = true,
+                "--persist" => parsed.no_persist = false,
+                "-h" | "--help" => {
+                    println!("{USAGE}");
+                    std::process::exit(0);
+                }
+                _ => bail!("unknown argument `{arg}`\n\n{USAGE}"),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn validated(self) -> Result<Self> {
+        if self.replay_speed.is_nan() || self.replay_speed < 0.0 {
+            bail!("`--replay-speed` must be a non-negative number");
+        }
+        if self.bitrate == 0 {
+            bail!("`--bitrate` must be at least 1");
+        }
+        if self.col_width == Some(0) {
+            bail!("`--col-width` must be at least 1");
+        }
+        if self.max_history_cols == Some(0) {
+            bail!("`--max-history-cols` must be at least 1");
+        }
+        if self.poll_interval.is_zero() {
+            bail!("`--poll-interval` must be positive");
+        }
+        if self.history == 0 {
+            bail!("`--history` must be at least 1");
+        }
+        Ok(self)
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("`{flag}` requires a value\n\n{USAGE}"))
+}
+
+fn parse<T>(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Into<Error>,
+{
+    value(args, flag)?
+        .parse()
+        .map_err(|e: T::Err| e.into().context(format!("invalid value for `{flag}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn config(options: &[&str]) -> Args {
+        Args::parse_from(args(options), Args::default()).unwrap()
+    }
+
+    fn triggers(parsed: &Args) -> Vec<String> {
+        parsed.triggers.iter().map(Trigger::to_string).collect()
+    }
+
+    #[test]
+    fn command_line_overrides_the_config_file() {
+        let defaults = config(&[
+            "--device",
+            "/dev/ttyUSB0",
+            "--history",
+            "10",
+            "--wait-for",
+            "100,200",
+            "--trigger",
+            "123#01",
+            "--trigger",
+            "456#02",
+        ]);
+        let cli = args(&[
+            "--history",
+            "20",
+            "--wait-for",
+            "300",
+            "--trigger",
+            "789#03",
+        ]);
+        let parsed = Args::parse_from(cli, defaults).unwrap();
+        assert_eq!(parsed.device, PathBuf::from("/dev/ttyUSB0"));
+        assert_eq!(parsed.history, 20);
+        assert_eq!(parsed.wait_for, [0x300]);
+        assert_eq!(triggers(&parsed), ["789#03"]);
+    }
+
+    #[test]
+    fn config_lists_stay_unless_given_on_the_command_line() {
+        let defaults = config(&["--wait-for", "100", "--trigger", "123#01"]);
+        let parsed = Args::parse_from(args(&["--bell"]), defaults).unwrap();
+        assert!(parsed.bell);
+        assert_eq!(parsed.wait_for, [0x100]);
+        assert_eq!(triggers(&parsed), ["123#01"]);
+    }
+
+    #[test]
+    fn command_line_turns_off_config_flags() {
+        let defaults = config(&["--bell", "--stream", "--no-persist"]);
+        let parsed = Args::parse_from(args(&["--no-bell", "--persist"]), defaults).unwrap();
+        assert!(!parsed.bell);
+        assert!(parsed.stream);
+        assert!(!parsed.no_persist);
+    }
+
+    #[test]
+    fn only_the_merged_options_are_validated() {
+        let defaults = config(&["--history", "0"]);
+        let parsed = Args::parse_from(args(&["--history", "5"]), defaults).unwrap();
+        assert_eq!(parsed.validated().unwrap().history, 5);
+        assert!(config(&["--history", "0"]).validated().is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! Defaults for the command-line options from `canalyzer.toml`. The file
+//! holds top-level `key = value` pairs named after the options, e.g.
+//!
+//! ```toml
+//! device = "/dev/ttyUSB0"
+//! baud = 500000
+//! sink-stale = true
+//! trigger = ["123#01", "456#ff"]
+//! ```
+//!
+//! Values are strings, numbers, booleans or arrays of them. An array
+//! repeats `trigger`, gives `diff` both of its files and is joined with
+//! commas for the other options, e.g. `wait-for = ["100", "200"]`.
+//! Underscores in keys stand for dashes.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+
+const NAME: &str = "canalyzer.toml";
+
+enum Value {
+    Flag(bool),
+    Values(Vec<String>),
+}
+
+/// The options set by a config file, in the order they were written.
+pub struct Config {
+    options: Vec<(String, Value)>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut options = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                bail!("line {}: expected `key = value`", i + 1);
+            };
+            let key = key.trim().replace('_', "-");
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                bail!("line {}: invalid key `{}`", i + 1, key);
+            }
+            if key == "config" || key == "help" {
+                bail!("line {}: `{key}` can't be set in a config file", i + 1);
+            }
+            let value = parse_value(value.trim())
+                .with_context(|| format!("line {}: invalid value for `{key}`", i + 1))?;
+            options.push((key, value));
+        }
+        Ok(Self { options })
+    }
+
+    /// The file's options as command-line arguments, to be parsed into the
+    /// defaults that the real ones are parsed over.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (key, value) in &self.options {
+            match value {
+                Value::Flag(true) => args.push(format!("--{key}")),
+                Value::Flag(false) => {}
+                Value::Values(values) => match key.as_str() {
+                    "trigger" => {
+                        for value in values {
+                            args.push(format!("--{key}"));
+                            args.push(value.clone());
+                        }
+                    }
+                    "diff" => {
+                        args.push(format!("--{key}"));
+                        args.extend(values.iter().cloned());
+                    }
+                    _ => {
+                        args.push(format!("--{key}"));
+                        args.push(values.join(","));
+                    }
+                },
+            }
+        }
+        args
+    }
+}
+
+/// `./canalyzer.toml`, or else `canalyzer.toml` next to the state file, if
+/// either exists.
+pub fn default_path() -> Option<PathBuf> {
+    let local = PathBuf::from(NAME);
+    if local.is_file() {
+        return Some(local);
+    }
+    let global = crate::state::default_path()?.with_file_name(NAME);
+    global.is_file().then_some(global)
+}
+
+fn parse_value(s: &str) -> Result<Value> {
+    match s {
+        "true" => return Ok(Value::Flag(true)),
+        "false" => return Ok(Value::Flag(false)),
+        _ => {}
+    }
+    let Some(items) = s.strip_prefix('[') else {
+        return match scalar(s)? {
+            (value, "") => Ok(Value::Values(vec![value])),
+            (_, tail) => bail!("unexpected `{tail}`"),
+        };
+    };
+    let Some(items) = items.strip_suffix(']') else {
+        bail!("unterminated array");
+    };
+    let mut values = Vec::new();
+    let mut rest = items.trim();
+    while !rest.is_empty() {
+        let (value, tail) = scalar(rest)?;
+        values.push(value);
+        rest = tail.trim_start();
+        match rest.strip_prefix(',') {
+            Some(tail) => rest = tail.trim_start(),
+            None if rest.is_empty() => {}
+            None => bail!("expected `,` between array items"),
+        }
+    }
+    Ok(Value::Values(values))
+}
+
+/// A string or bare number at the start of `s`, and what follows it.
+fn scalar(s: &str) -> Result<(String, &str)> {
+    let Some(quoted) = s.strip_prefix('"') else {
+        let end = s.find([',', ' ', '\t']).unwrap_or(s.len());
+        let number = &s[..end];
+        if number.parse::<f64>().is_err() {
+            bail!("expected a string, number or boolean, got `{number}`");
+        }
+        return Ok((number.to_string(), &s[end..]));
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &quoted[i + 1..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                _ => bail!("unsupported escape in string"),
+            },
+            c => value.push(c),
+        }
+    }
+    bail!("unterminated string")
+}
+
+/// Drops a `#` comment, unless the `#` is inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        Config::parse(text).unwrap().args()
+    }
+
+    #[test]
+    fn arrays_become_the_arguments_their_option_takes() {
+        assert_eq!(
+            args(r#"trigger = ["123#01", "456#ff"]"#),
+            ["--trigger", "123#01", "--trigger", "456#ff"]
+        );
+        assert_eq!(
+            args(r#"diff = ["a.json", "b.json"]"#),
+            ["--diff", "a.json", "b.json"]
+        );
+        assert_eq!(
+            args(r#"wait-for = ["100", "200"]"#),
+            ["--wait-for", "100,200"]
+        );
+        assert_eq!(args("history = 10"), ["--history", "10"]);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::canbus::{Message, Transmit};
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryIter},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+pub struct Entry {
+    pub frame: Message,
+    pub period: Duration,
+    pub paused: bool,
+    next: Instant,
+}
+
+/// Sends frames every `period` from a thread of its own, like a cyclic task
+/// keeping a node awake. Entries are shared with the thread, so a frame
+/// edited in place goes out on its next cycle. An entry whose frame can't
+/// be sent is paused and the error reported through [`Cyclic::errors`].
+/// The thread sleeps until the next entry is due, or until the entries
+/// change, and ends once the `Cyclic` is dropped.
+pub struct Cyclic {
+    entries: Arc<Mutex<Vec<Entry>>>,
+    errors: Receiver<String>,
+    wake: Sender<()>,
+}
+
+impl Cyclic {
+    pub fn spawn(transmitter: Arc<Mutex<Box<dyn Transmit>>>) -> Self {
+        let entries = Arc::new(Mutex::new(Vec::<Entry>::new()));
+        let shared = Arc::clone(&entries);
+        let (tx, errors) = channel();
+        let (wake, woken) = channel();
+        std::thread::spawn(move || loop {
+            let mut entries = shared.lock().unwrap();
+            let now = Instant::now();
+            for entry in entries.iter_mut().filter(|e| !e.paused && e.next <= now) {
+                if let Err(e) = transmitter.lock().unwrap().transmit(&entry.frame) {
+                    entry.paused = true;
+                    let _ = tx.send(format!("cyclic {} paused: {e}", entry.frame));
+                }
+                // Skip cycles missed while the thread was held up
+                // instead of sending a burst to catch up.
+                entry.next = (entry.next + entry.period).max(now);
+            }
+            let next = entries.iter().filter(|e| !e.paused).map(|e| e.next).min();
+            drop(entries);
+            let woken = match next {
+                Some(next) => woken.recv_timeout(next.saturating_duration_since(Instant::now())),
+                None => woken.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            if let Err(RecvTimeoutError::Disconnected) = woken {
+                return;
+            }
+        });
+        Self {
+            entries,
+            errors,
+            wake,
+        }
+    }
+
+    pub fn entries(&self) -> MutexGuard<'_, Vec<Entry>> {
+        self.entries.lock().unwrap()
+    }
+
+    /// Has the thread look at the entries again after they changed.
+    fn wake(&self) {
+        let _ = self.wake.send(());
+    }
+
+    pub fn add(&self, frame: Message, period: Duration) {
+        self.entries().push(Entry {
+            frame,
+            period,
+            paused: false,
+            next: Instant::now(),
+        });
+        self.wake();
+    }
+
+    /// Replaces the frame and period of entry `i` without resetting its
+    /// schedule.
+    pub fn edit(&self, i: usize, frame: Message, period: Duration) {
+        if let Some(entry) = self.entries().get_mut(i) {
+            entry.frame = frame;
+            entry.period = period;
+        }
+        self.wake();
+    }
+
+    pub fn remove(&self, i: usize) {
+        let mut entries = self.entries();
+        if i < entries.len() {
+            entries.remove(i);
+        }
+        drop(entries);
+        self.wake();
+    }
+
+    pub fn toggle_paused(&self, i: usize) {
+        if let Some(entry) = self.entries().get_mut(i) {
+            entry.paused = !entry.paused;
+            entry.next = Instant::now();
+        }
+        self.wake();
+    }
+
+    pub fn errors(&self) -> TryIter<'_, String> {
+        self.errors.try_iter()
+    }
+}
+
+/// Parses `<id>#<data> <ms>`, a frame and its period in milliseconds.
+pub fn parse(entry: &str) -> Option<(Message, Duration)> {
+    let (frame, period) = entry.trim().split_once(' ')?;
+    let period = period.trim().parse().ok().filter(|&ms| ms > 0)?;
+    Some((
+        crate::canbus::parse_frame(frame)?,
+        Duration::from_millis(period),
+    ))
+}
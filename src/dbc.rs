@@ -0,0 +1,153 @@
+//! Just enough of the DBC format to decode signals: `BO_` message
+//! definitions and the `SG_` signals that follow them. Everything else in
+//! the file (nodes, value tables, attributes, comments) is skipped.
+
+use anyhow::{bail, Context, Result};
+use std::{collections::HashMap, fs, path::Path};
+
+/// Set on `BO_` IDs that are 29-bit extended.
+const EXTENDED_FLAG: u32 = 0x8000_0000;
+
+pub struct Database {
+    messages: HashMap<u32, MessageDef>,
+}
+
+pub struct MessageDef {
+    pub name: String,
+    pub signals: Vec<Signal>,
+}
+
+pub struct Signal {
+    pub name: String,
+    start: u32,
+    len: u32,
+    little_endian: bool,
+    signed: bool,
+    factor: f64,
+    offset: f64,
+    pub unit: String,
+}
+
+impl Database {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("cannot read DBC file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("cannot parse DBC file {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut messages = HashMap::new();
+        let mut current = None;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if let Some(def) = line.strip_prefix("BO_ ") {
+                let Some((id, name)) = parse_message(def) else {
+                    bail!("line {}: malformed message definition", n + 1);
+                };
+                messages.insert(
+                    id,
+                    MessageDef {
+                        name,
+                        signals: Vec::new(),
+                    },
+                );
+                current = Some(id);
+            } else if line.starts_with("SG_ ") {
+                let Some(msg) = current.and_then(|id| messages.get_mut(&id)) else {
+                    bail!("line {}: signal outside of a message", n + 1);
+                };
+                let Some(signal) = parse_signal(line) else {
+                    bail!("line {}: malformed signal definition", n + 1);
+                };
+                msg.signals.push(signal);
+            } else if line.is_empty() {
+                current = None;
+            }
+        }
+        Ok(Self { messages })
+    }
+
+    pub fn get(&self, id: u32) -> Option<&MessageDef> {
+        self.messages.get(&id)
+    }
+
+    /// Message names by ID.
+    pub fn names(&self) -> HashMap<u32, String> {
+        self.messages
+            .iter()
+            .map(|(&id, def)| (id, def.name.clone()))
+            .collect()
+    }
+}
+
+impl Signal {
+    /// Physical value of the signal in `data`, or `None` if the payload is
+    /// too short to hold it.
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        let bit = |i: u32| -> Option<u64> {
+            let byte = data.get(i as usize / 8)?;
+            Some(u64::from(byte >> (i % 8) & 1))
+        };
+        let mut raw = 0u64;
+        if self.little_endian {
+            for i in (0..self.len).rev() {
+                raw = raw << 1 | bit(self.start + i)?;
+            }
+        } else {
+            // Motorola signals start at their most significant bit and walk
+            // down each byte before moving to the next one.
+            let mut i = self.start;
+            for _ in 0..self.len {
+                raw = raw << 1 | bit(i)?;
+                i = if i.is_multiple_of(8) { i + 15 } else { i - 1 };
+            }
+        }
+        let value = if self.signed && self.len < 64 && raw >> (self.len - 1) & 1 == 1 {
+            (raw | !0 << self.len) as i64 as f64
+        } else if self.signed {
+            raw as i64 as f64
+        } else {
+            raw as f64
+        };
+        Some(value * self.factor + self.offset)
+    }
+}
+
+/// Formats a decoded value without trailing zeros, e.g. `12.5` or `3`.
+pub fn format_value(value: f64) -> String {
+    let s = format!("{value:.3}");
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Parses `<id> <name>: <dlc> <sender>`.
+fn parse_message(def: &str) -> Option<(u32, String)> {
+    let mut words = def.split_whitespace();
+    let id: u32 = words.next()?.parse().ok()?;
+    let name = words.next()?.strip_suffix(':')?;
+    Some((id & !EXTENDED_FLAG, name.to_string()))
+}
+
+/// Parses `SG_ <name> [mux] : <start>|<len>@<order><sign> (<factor>,<offset>)
+/// [<min>|<max>] "<unit>" <receivers>`.
+fn parse_signal(line: &str) -> Option<Signal> {
+    let (head, spec) = line.split_once(':')?;
+    let name = head.split_whitespace().nth(1)?;
+    let mut words = spec.split_whitespace();
+    let (start, layout) = words.next()?.split_once('|')?;
+    let (len, layout) = layout.split_once('@')?;
+    let (factor, offset) = words
+        .next()?
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split_once(',')?;
+    Some(Signal {
+        name: name.to_string(),
+        start: start.parse().ok()?,
+        len: len.parse().ok().filter(|len| (1..=64).contains(len))?,
+        little_endian: layout.starts_with('1'),
+        signed: layout.ends_with('-'),
+        factor: factor.parse().ok()?,
+        offset: offset.parse().ok()?,
+        unit: spec.split('"').nth(1).unwrap_or_default().to_string(),
+    })
+}
@@ -0,0 +1,90 @@
+//! `--diff`: compares the latest payloads of two saved sessions, ID by ID.
+
+use crate::export;
+use anyhow::Result;
+use crossterm::style::Stylize;
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+/// Sorts like the ID sort mode, with frames from different sources and
+/// error frames kept apart from data frames of the same ID.
+type Key = (Option<u32>, String, String, bool);
+
+/// Prints the IDs only one of the dumps has, then the IDs whose latest
+/// payloads differ with the differing bytes. With `color` those bytes are
+/// also shown reversed.
+pub fn report(a: &Path, b: &Path, out: &mut impl Write, color: bool) -> Result<()> {
+    let (old, new) = (latest(a)?, latest(b)?);
+    for (path, from, other) in [(a, &old, &new), (b, &new, &old)] {
+        let only: Vec<_> = from
+            .keys()
+            .filter(|key| !other.contains_key(*key))
+            .map(label)
+            .collect();
+        if !only.is_empty() {
+            writeln!(out, "only in {}: {}", path.display(), only.join(", "))?;
+        }
+    }
+    let mut same = 0;
+    for (key, before) in &old {
+        let Some(after) = new.get(key) else {
+            continue;
+        };
+        let differing: Vec<_> = (0..before.len().max(after.len()))
+            .filter(|&i| before.get(i) != after.get(i))
+            .collect();
+        if differing.is_empty() {
+            same += 1;
+            continue;
+        }
+        let bytes: Vec<_> = differing.iter().map(usize::to_string).collect();
+        writeln!(
+            out,
+            "{}: {} -> {} (bytes {})",
+            label(key),
+            payload(before, &differing, color),
+            payload(after, &differing, color),
+            bytes.join(", ")
+        )?;
+    }
+    match same {
+        1 => writeln!(out, "1 ID is the same in both")?,
+        n => writeln!(out, "{n} IDs are the same in both")?,
+    }
+    Ok(())
+}
+
+fn latest(path: &Path) -> Result<BTreeMap<Key, Vec<u8>>> {
+    Ok(export::load(path)?
+        .into_iter()
+        .filter_map(|msg| {
+            let data = msg.values.back()?.bytes();
+            Some(((msg.id_value, msg.id, msg.source, msg.is_error), data))
+        })
+        .collect())
+}
+
+fn label((_, id, source, is_error): &Key) -> String {
+    let mut label = id.clone();
+    if !source.is_empty() {
+        label += &format!(" on {source}");
+    }
+    if *is_error {
+        label += " (error)";
+    }
+    label
+}
+
+fn payload(data: &[u8], differing: &[usize], color: bool) -> String {
+    if data.is_empty() {
+        return "(empty)".to_string();
+    }
+    let bytes: Vec<_> = data
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| match color && differing.contains(&i) {
+            true => format!("{byte:02X}").reverse().to_string(),
+            false => format!("{byte:02X}"),
+        })
+        .collect();
+    bytes.join(" ")
+}
@@ -0,0 +1,302 @@
+use crate::{
+    canbus::{self, FrameKind, CAN_ERR_FLAG},
+    Message, Value,
+};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Reads back a dump written by [`json`].
+pub fn load(path: &Path) -> Result<Vec<Message>> {
+    let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    let mut msgs: Vec<Message> = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("cannot parse {}", path.display()))?;
+    for msg in &mut msgs {
+        msg.id_value = canbus::parse_id(&msg.id);
+        // Dumps from before the count and first frame were recorded only
+        // have the history.
+        if msg.count == 0 {
+            msg.count = msg.values.len() as u64;
+        }
+        if msg.first_seen == UNIX_EPOCH {
+            msg.first_seen = msg.values.front().map_or(UNIX_EPOCH, |value| value.ts);
+        }
+    }
+    Ok(msgs)
+}
+
+pub fn json(path: &Path, msgs: &[Message]) -> Result<()> {
+    write(path, |out| {
+        serde_json::to_writer_pretty(&mut *out, msgs)?;
+        writeln!(out)?;
+        Ok(())
+    })
+}
+
+/// Copies of `msgs` whose timestamps count from `start` instead of the Unix
+/// epoch, for `--relative-time`. Every export written from them follows.
+pub fn rebased<'a>(msgs: impl IntoIterator<Item = &'a Message>, start: SystemTime) -> Vec<Message> {
+    msgs.into_iter()
+        .map(|msg| {
+            let mut msg = msg.clone();
+            msg.first_seen = rebase(msg.first_seen, start);
+            for value in msg.values.iter_mut().chain(&mut msg.baseline) {
+                value.ts = rebase(value.ts, start);
+            }
+            msg
+        })
+        .collect()
+}
+
+/// `ts` as if the Unix epoch were `start`. Times before it become zero.
+pub fn rebase(ts: SystemTime, start: SystemTime) -> SystemTime {
+    UNIX_EPOCH + ts.duration_since(start).unwrap_or_default()
+}
+
+/// Writes one `timestamp_ms,id,data` row per captured frame. Timestamps are
+/// milliseconds since the Unix epoch, as in the JSON dump.
+pub fn csv<'a>(path: &Path, msgs: impl IntoIterator<Item = &'a Message>) -> Result<()> {
+    write(path, |out| {
+        writeln!(out, "timestamp_ms,id,data")?;
+        for msg in msgs {
+            for value in &msg.values {
+                let ts = value.ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+                writeln!(out, "{},{},{}", ts.as_millis(), msg.id, value.data)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Writes a `candump -L` log of every captured frame in timestamp order, so
+/// it can be replayed with `--replay` or fed to can-utils.
+pub fn candump<'a>(
+    path: &Path,
+    interface: &str,
+    msgs: impl IntoIterator<Item = &'a Message>,
+) -> Result<()> {
+    let mut frames: Vec<_> = msgs
+        .into_iter()
+        .flat_map(|msg| msg.values.iter().map(move |value| (msg, value)))
+        .collect();
+    frames.sort_by_key(|(_, value)| value.ts);
+    write(path, |out| {
+        for (msg, value) in frames {
+            candump_line(out, interface, msg, value)?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes one frame as a `candump -L` line, on the interface it was read on
+/// or else on `interface`.
+pub fn candump_line(
+    out: &mut impl Write,
+    interface: &str,
+    msg: &Message,
+    value: &Value,
+) -> io::Result<()> {
+    let ts = value.ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let interface = match msg.source.as_str() {
+        "" => interface,
+        source => source,
+    };
+    let id = match msg.id_value {
+        Some(id) if msg.is_error => format!("{:08X}", id | CAN_ERR_FLAG),
+        Some(id) if msg.is_extended => format!("{id:08X}"),
+        Some(id) => format!("{id:03X}"),
+        None => msg.id.clone(),
+    };
+    let data = match value.kind {
+        FrameKind::Remote if value.dlc > 0 => format!("R{}", value.dlc),
+        FrameKind::Remote => "R".to_string(),
+        FrameKind::Data | FrameKind::Error => value.data.clone(),
+    };
+    writeln!(
+        out,
+        "({}.{:06}) {interface} {id}#{data}",
+        ts.as_secs(),
+        ts.subsec_micros()
+    )
+}
+
+/// `LINKTYPE_CAN_SOCKETCAN`, which Wireshark dissects as SocketCAN frames.
+const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+
+/// Writes a pcapng capture of every captured frame in timestamp order, as
+/// SocketCAN frames with microsecond timestamps. Frames with more than eight
+/// bytes are marked as CAN FD.
+pub fn pcapng<'a>(path: &Path, msgs: impl IntoIterator<Item = &'a Message>) -> Result<()> {
+    let mut frames: Vec<_> = msgs
+        .into_iter()
+        .flat_map(|msg| msg.values.iter().map(move |value| (msg, value)))
+        .collect();
+    frames.sort_by_key(|(_, value)| value.ts);
+    write(path, |out| {
+        // Section header: byte-order magic, version 1.0, unknown length.
+        let mut shb = 0x1A2B_3C4Du32.to_le_bytes().to_vec();
+        shb.extend(1u16.to_le_bytes());
+        shb.extend(0u16.to_le_bytes());
+        shb.extend((-1i64).to_le_bytes());
+        block(out, 0x0A0D_0D0A, &shb)?;
+        // Interface description: link type, reserved, no snapshot limit.
+        let mut idb = LINKTYPE_CAN_SOCKETCAN.to_le_bytes().to_vec();
+        idb.extend([0, 0]);
+        idb.extend(0u32.to_le_bytes());
+        block(out, 1, &idb)?;
+        for (msg, value) in frames {
+            let packet = socketcan_packet(msg, value);
+            let ts = value.ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let micros = ts.as_micros() as u64;
+            let mut epb = 0u32.to_le_bytes().to_vec();
+            epb.extend(((micros >> 32) as u32).to_le_bytes());
+            epb.extend((micros as u32).to_le_bytes());
+            epb.extend((packet.len() as u32).to_le_bytes());
+            epb.extend((packet.len() as u32).to_le_bytes());
+            epb.extend(&packet);
+            block(out, 6, &epb)?;
+        }
+        Ok(())
+    })
+}
+
+/// A pcapng block, its body padded to 32 bits.
+fn block(out: &mut impl Write, kind: u32, body: &[u8]) -> io::Result<()> {
+    let padding = (4 - body.len() % 4) % 4;
+    let len = (12 + body.len() + padding) as u32;
+    out.write_all(&kind.to_le_bytes())?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(body)?;
+    out.write_all(&[0; 3][..padding])?;
+    out.write_all(&len.to_le_bytes())
+}
+
+/// A frame as `LINKTYPE_CAN_SOCKETCAN` has it: the ID and flags in network
+/// byte order, the payload length, FD flags, two reserved bytes and the
+/// payload.
+fn socketcan_packet(msg: &Message, value: &Value) -> Vec<u8> {
+    let mut id = msg.id_value.unwrap_or_default();
+    if msg.is_extended {
+        id |= 0x8000_0000;
+    }
+    if value.kind == FrameKind::Remote {
+        id |= 0x4000_0000;
+    }
+    if msg.is_error {
+        id |= CAN_ERR_FLAG;
+    }
+    let data = value.bytes();
+    let len = match value.kind {
+        FrameKind::Remote => value.dlc,
+        FrameKind::Data | FrameKind::Error => data.len(),
+    };
+    let mut packet = id.to_be_bytes().to_vec();
+    packet.push(len as u8);
+    // CANFD_FDF, so the frame isn't mistaken for a malformed classic one.
+    packet.push(if data.len() > 8 { 0x04 } else { 0 });
+    packet.extend([0, 0]);
+    packet.extend(data);
+    packet
+}
+
+/// One frame as a line of JSON, its `Value` fields alongside the ID's.
+pub fn json_line(out: &mut impl Write, msg: &Message, value: &Value) -> io::Result<()> {
+    #[derive(Serialize)]
+    struct Frame<'a> {
+        id: &'a str,
+        #[serde(skip_serializing_if = "str::is_empty")]
+        source: &'a str,
+        is_extended: bool,
+        is_error: bool,
+        #[serde(flatten)]
+        value: &'a Value,
+    }
+    let frame = Frame {
+        id: &msg.id,
+        source: &msg.source,
+        is_extended: msg.is_extended,
+        is_error: msg.is_error,
+        value,
+    };
+    serde_json::to_writer(&mut *out, &frame)?;
+    writeln!(out)
+}
+
+fn write(path: &Path, f: impl FnOnce(&mut BufWriter<File>) -> Result<()>) -> Result<()> {
+    let mut out = BufWriter::new(
+        File::create(path).with_context(|| format!("cannot create {}", path.display()))?,
+    );
+    f(&mut out)
+        .and_then(|()| Ok(out.flush()?))
+        .with_context(|| format!("cannot write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        canbus::{Event, ReplaySource, Source},
+        cli, ingest, row_key,
+        state::State,
+    };
+
+    fn ingest_all(frames: impl IntoIterator<Item = canbus::Message>) -> Vec<Message> {
+        let (args, persisted) = (cli::Args::default(), State::default());
+        let mut msgs = Vec::new();
+        let mut index = crate::index(&msgs);
+        for frame in frames {
+            ingest(&mut msgs, &mut index, frame, &args, &persisted);
+        }
+        msgs
+    }
+
+    #[test]
+    fn candump_export_replays_into_the_same_rows() {
+        let frames = [
+            "0101#0102",
+            "0101#0103",
+            "0a1#FF",
+            "12345678#DEADBEEF",
+            "00000101#01",
+            "7FF#R",
+        ]
+        .map(|frame| canbus::Message {
+            source: "can0".to_string(),
+            ..canbus::parse_frame(frame).unwrap()
+        });
+        let msgs = ingest_all(frames);
+        let path = std::env::temp_dir().join(format!("canalyzer-{}.log", std::process::id()));
+        candump(&path, "can0", &msgs).unwrap();
+        let replayed = Box::new(ReplaySource {
+            path: path.clone(),
+            speed: 0.0,
+        })
+        .messages();
+        let replayed = ingest_all(replayed.iter().map(|event| match event {
+            Event::Frame(frame) => frame,
+            _ => panic!("the export has a line that isn't a frame"),
+        }));
+        std::fs::remove_file(&path).unwrap();
+
+        let rows = |msgs: &[Message]| -> Vec<_> {
+            msgs.iter()
+                .map(|msg| {
+                    let key = row_key(&msg.source, &msg.id, msg.is_extended, msg.is_error);
+                    let data: Vec<_> = msg
+                        .values
+                        .iter()
+                        .map(|v| (v.kind, v.data.clone()))
+                        .collect();
+                    (key, data)
+                })
+                .collect()
+        };
+        assert_eq!(msgs.len(), 5);
+        assert_eq!(rows(&replayed), rows(&msgs));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{canbus, export, Message};
+use anyhow::{anyhow, Context, Result};
+use std::{
+    fs::OpenOptions,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends every received frame to a `candump -L` log from a thread of its
+/// own, so disk writes never stall the UI. The log is flushed at least once
+/// a second and when the logger is finished.
+pub struct Logger {
+    path: PathBuf,
+    tx: Sender<canbus::Message>,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl Logger {
+    pub fn create(path: &Path, interface: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open log file {}", path.display()))?;
+        let interface = interface.to_string();
+        let (tx, rx) = channel::<canbus::Message>();
+        let thread = std::thread::spawn(move || {
+            let mut out = BufWriter::new(file);
+            let mut flushed = Instant::now();
+            loop {
+                match rx.recv_timeout(FLUSH_INTERVAL) {
+                    Ok(frame) => {
+                        let msg = Message::from(frame);
+                        export::candump_line(&mut out, &interface, &msg, &msg.values[0])?;
+                        if flushed.elapsed() < FLUSH_INTERVAL {
+                            continue;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return out.flush(),
+                }
+                out.flush()?;
+                flushed = Instant::now();
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+            tx,
+            thread,
+        })
+    }
+
+    pub fn log(&self, frame: &canbus::Message) {
+        // A failed write ends the thread; the error is reported by `finish`.
+        let _ = self.tx.send(frame.clone());
+    }
+
+    /// Flushes the log and reports the first write error, if any.
+    pub fn finish(self) -> Result<()> {
+        drop(self.tx);
+        self.thread
+            .join()
+            .map_err(|_| anyhow!("log writer panicked"))?
+            .with_context(|| format!("cannot write log file {}", self.path.display()))
+    }
+}
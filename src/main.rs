@@ -1,64 +1,530 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use canbus::{FrameKind, Source};
 use crossterm::ExecutableCommand;
 use ratatui::{
     prelude::*,
-    widgets::{block::Position, *},
+    widgets::{
+        block::{Position, Title},
+        *,
+    },
 };
-use serde::Serialize;
+use regex::Regex;
+use sequence::Sequence;
+use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, TimestampMilliSeconds};
-use std::{cmp::Ordering, iter, time::SystemTime};
+use state::State;
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    io::Write,
+    iter,
+    path::Path,
+    sync::{mpsc::Receiver, Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use trigger::Trigger;
+use watch::Watch;
 
 mod canbus;
+mod checksum;
+mod cli;
+mod config;
+mod cyclic;
+mod dbc;
+mod diff;
+mod export;
+mod logger;
+mod metrics;
+mod mqtt;
+mod regex;
+mod rules;
+mod sequence;
+mod serial;
+#[cfg(target_os = "linux")]
+mod socketcan;
+mod state;
+mod theme;
+mod trigger;
+mod watch;
+mod websocket;
+
+/// Unparseable input lines kept for the U overlay.
+const RECENT_BAD_LINES: usize = 20;
+
+/// Frames kept for recalling in the transmit prompt.
+const SENT_HISTORY: usize = 20;
+/// Raw input lines shown by the U panel.
+const RAW_LINES: usize = 10;
+/// Events handled per pass of the UI loop. A busy bus can queue more than
+/// that between draws; the rest wait for the next pass so that keys and
+/// repaints aren't starved.
+const BATCH: usize = 5000;
+/// Without frames or keys the screen is only repainted this often, to keep
+/// ages, rates and fading backgrounds current.
+const REPAINT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The group of rows without tags.
+const UNTAGGED: &str = "untagged";
+
+/// Every key binding, shown by the `?` overlay.
+const HELP: &[(&str, &str)] = &[
+    ("f", "toggle filter mode (select rows)"),
+    (
+        "Up/Down",
+        "move the selection, or a cursor outside filter mode",
+    ),
+    ("PgUp/PgDn", "page the selection or the table"),
+    ("Home/End", "jump to the first or last row"),
+    ("j", "jump the selection to an ID"),
+    ("v", "mark the selected ID for bulk ignore or pin"),
+    ("i", "ignore the selected or marked IDs, else the cursor's"),
+    ("I", "add or remove patterns of IDs to ignore"),
+    ("p", "pin the selected or marked IDs, else the cursor's"),
+    ("r", "reset the selected ID's history"),
+    ("N", "write a note on the selected ID"),
+    ("T", "tag the selected ID, e.g. powertrain, body"),
+    ("G", "group rows by their first tag"),
+    ("C", "collapse or expand a group"),
+    ("b", "diff the selected ID against its current value"),
+    ("y", "copy the selected ID, payload and time"),
+    ("t", "arm or disarm a trigger on the selected ID's payload"),
+    ("Left/Right", "choose a byte of the selected ID"),
+    ("a", "toggle the selected ID's ASCII sidebar"),
+    ("[/]", "read fewer or more bytes from it as one integer"),
+    ("E", "toggle that integer's byte order"),
+    ("-", "toggle whether that integer is signed"),
+    ("g", "toggle a graph of the chosen bytes' history"),
+    ("m", "don't highlight changes in that byte"),
+    ("K", "count frames dropped by that byte's sequence counter"),
+    ("S", "toggle the min, max and last value of each byte"),
+    ("B", "toggle the latest payload's bits"),
+    ("/", "search IDs"),
+    ("e", "filter IDs and payloads by regex"),
+    ("R", "filter IDs to a range, e.g. 100..1ff"),
+    ("o", "show only ignored IDs"),
+    ("Esc", "clear all filters and the cursor"),
+    ("s", "sort by recency, ID, rate or count"),
+    ("h", "cycle hex, decimal and ASCII payloads"),
+    ("z", "toggle the rate column"),
+    ("l", "toggle the last-seen time column"),
+    ("#", "toggle the frame count column"),
+    ("L", "toggle compact rows with only the latest value"),
+    ("</>", "show fewer or more past values"),
+    ("n", "toggle DBC message names in the ID column"),
+    ("Space", "pause or resume capture"),
+    ("c", "clear all messages"),
+    ("x", "export the view as CSV"),
+    ("d", "export the view as a candump log"),
+    ("w", "transmit a frame, Up/Down recalls sent ones"),
+    ("W", "add, edit, pause or remove cyclic frames"),
+    ("u", "show the last input lines that weren't frames"),
+    ("U", "toggle the last raw lines read from the device"),
+    ("?", "show this help"),
+    ("q", "quit"),
+];
+
+/// How payload bytes are rendered in the value cells.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DisplayMode {
+    #[default]
+    Hex,
+    Decimal,
+    Ascii,
+}
+
+impl DisplayMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Hex => Self::Decimal,
+            Self::Decimal => Self::Ascii,
+            Self::Ascii => Self::Hex,
+        }
+    }
+
+    /// Width of a value cell holding a payload of `bytes` bytes, with room
+    /// for the RTR badge of empty ones.
+    fn width(self, bytes: usize) -> u16 {
+        let width = match self {
+            Self::Hex => 2 * bytes,
+            Self::Decimal => (4 * bytes).saturating_sub(1),
+            Self::Ascii => bytes,
+        };
+        width.max(3) as u16
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            Self::Decimal => " ",
+            Self::Hex | Self::Ascii => "",
+        }
+    }
+
+    fn render(self, byte: &str) -> Cow<'_, str> {
+        let Ok(value) = u8::from_str_radix(byte, 16) else {
+            return byte.into();
+        };
+        match self {
+            Self::Hex => byte.into(),
+            Self::Decimal => format!("{value:3}").into(),
+            Self::Ascii if value.is_ascii_graphic() || value == b' ' => {
+                char::from(value).to_string().into()
+            }
+            Self::Ascii => ".".into(),
+        }
+    }
+}
+
+/// Order of the rows below the pinned ones and above the ignored ones.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SortMode {
+    /// Most recently updated first.
+    #[default]
+    Recency,
+    /// Numerically ascending IDs.
+    Id,
+    /// Highest frame rate first.
+    Rate,
+    /// Most frames seen first.
+    Count,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Recency => Self::Id,
+            Self::Id => Self::Rate,
+            Self::Rate => Self::Count,
+            Self::Count => Self::Recency,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Recency => "recency",
+            Self::Id => "ID",
+            Self::Rate => "rate",
+            Self::Count => "count",
+        }
+    }
+}
+
+/// Ignored rows sort last and pinned rows first, then stale rows sink if
+/// the view asks for it; the sort mode orders the rest. Ties fall back to
+/// recency.
+///
+/// Ignoring outranks pinning, so a row that is both sorts first among the
+/// ignored ones rather than among the pinned ones. Two pinned or two
+/// ignored rows are ordered like any other pair. Grouping outranks both, so
+/// each group has its own pinned and ignored rows.
+fn compare(a: &Message, b: &Message, view: &View) -> Ordering {
+    let recency = || {
+        b.values
+            .back()
+            .unwrap()
+            .ts
+            .cmp(&a.values.back().unwrap().ts)
+    };
+    let grouped = match view.grouped {
+        true => group_key(a.group()).cmp(&group_key(b.group())),
+        false => Ordering::Equal,
+    };
+    grouped
+        .then(a.ignored.cmp(&b.ignored))
+        .then(b.pinned.cmp(&a.pinned))
+        .then_with(|| match view.sink_stale {
+            true => view.is_stale(a).cmp(&view.is_stale(b)),
+            false => Ordering::Equal,
+        })
+        .then_with(|| match view.sort {
+            SortMode::Recency => Ordering::Equal,
+            SortMode::Id => a.id_value.cmp(&b.id_value).then_with(|| a.id.cmp(&b.id)),
+            SortMode::Rate => b.rate(view).total_cmp(&a.rate(view)),
+            SortMode::Count => b.count.cmp(&a.count),
+        })
+        .then_with(recency)
+}
+
+/// Row filters; a message is shown only if it passes all of them.
+#[derive(Default)]
+struct Filter {
+    /// Case-insensitive substring of the ID.
+    query: String,
+    /// Matched against the ID or the latest payload.
+    regex: Option<Regex>,
+    /// Hides IDs outside it, including ones that aren't numbers.
+    id_range: Option<canbus::IdRange>,
+    /// Shows only ignored IDs, to review and un-ignore them.
+    only_ignored: bool,
+}
+
+impl Filter {
+    fn matches(&self, msg: &Message) -> bool {
+        msg.id
+            .to_ascii_uppercase()
+            .contains(&self.query.to_ascii_uppercase())
+            && self.regex.as_ref().is_none_or(|re| {
+                re.is_match(&msg.id) || re.is_match(&msg.values.back().unwrap().data)
+            })
+            && self
+                .id_range
+                .is_none_or(|range| msg.id_value.is_some_and(|id| range.contains(id)))
+            && (!self.only_ignored || msg.ignored)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Prompt {
+    Search,
+    Regex,
+    IdRange,
+    /// Typing an ID to select its row.
+    Jump,
+    IgnorePattern,
+    /// Editing the selected ID's note.
+    Note,
+    /// Editing the selected ID's tags.
+    Tags,
+    /// Typing a group to collapse or expand.
+    Collapse,
+    Transmit,
+    /// Adding a cyclic frame, or editing the one at the index.
+    Cyclic(Option<usize>),
+}
+
+/// Display settings that apply to every row. The toggles are saved with the
+/// state; the rest comes from the command line each time.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct View {
+    #[serde(skip)]
+    theme: theme::Theme,
+    mode: DisplayMode,
+    show_rate: bool,
+    show_count: bool,
+    show_time: bool,
+    show_names: bool,
+    show_ascii: bool,
+    show_graph: bool,
+    show_stats: bool,
+    show_bits: bool,
+    /// Only the latest value of each ID, diffed against the frame before.
+    compact: bool,
+    sort: SortMode,
+    /// Rows are grouped under their first tag, and the groups collapsed
+    /// here show only their header.
+    grouped: bool,
+    collapsed: BTreeSet<String>,
+    /// Rows with no frame for this long are badged; zero disables it.
+    #[serde(skip)]
+    stale_timeout: Duration,
+    #[serde(skip)]
+    sink_stale: bool,
+    /// Color changed bytes by whether they went up or down.
+    #[serde(skip)]
+    diff_direction: bool,
+    /// Time constant of the rate's moving average; zero shows the raw rate.
+    #[serde(skip)]
+    rate_smoothing: Duration,
+    /// Set while rows come from more than one source, to tell them apart.
+    #[serde(skip)]
+    show_source: bool,
+    /// At most this many value cells, however many fit.
+    #[serde(skip)]
+    max_cols: Option<usize>,
+    /// The start of the capture, with `--relative-time`.
+    #[serde(skip)]
+    relative_to: Option<SystemTime>,
+}
+
+impl View {
+    /// A receive time as the time of day, or with `--relative-time` as the
+    /// seconds since the capture started.
+    fn time(&self, ts: SystemTime) -> String {
+        match self.relative_to {
+            Some(start) => format!(
+                "+{:.3}",
+                ts.duration_since(start).unwrap_or_default().as_secs_f64()
+            ),
+            None => chrono::DateTime::<chrono::Local>::from(ts)
+                .format("%H:%M:%S%.3f")
+                .to_string(),
+        }
+    }
+
+    /// Whether the row is in a collapsed group.
+    fn hides(&self, msg: &Message) -> bool {
+        self.grouped && self.collapsed.contains(msg.group())
+    }
 
-#[derive(Serialize)]
+    fn is_stale(&self, msg: &Message) -> bool {
+        !self.stale_timeout.is_zero()
+            && msg
+                .values
+                .back()
+                .unwrap()
+                .ts
+                .elapsed()
+                .is_ok_and(|age| age >= self.stale_timeout)
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
 struct Message {
     id: String,
-    values: Vec<Value>,
+    /// The interface the frames were read on, so that the same ID on two
+    /// buses gets a row for each.
+    #[serde(default)]
+    source: String,
+    /// `id` parsed as a number, for sorting and lookups.
+    #[serde(skip)]
+    id_value: Option<u32>,
+    is_extended: bool,
+    is_error: bool,
+    values: VecDeque<Value>,
     ignored: bool,
     pinned: bool,
+    /// Byte positions whose changes aren't highlighted, e.g. counters.
+    #[serde(default)]
+    ignored_bytes: BTreeSet<usize>,
+    /// Frames seen since start, including those dropped from `values`.
+    #[serde(default)]
+    count: u64,
+    /// When the first of those frames arrived.
+    #[serde_as(as = "TimestampMilliSeconds")]
+    #[serde(default = "unix_epoch")]
+    first_seen: SystemTime,
+    /// A frozen value every frame is diffed against instead of its
+    /// predecessor.
+    #[serde(default)]
+    baseline: Option<Value>,
+    /// What the ID is thought to be, e.g. "door lock command".
+    #[serde(default)]
+    note: Option<String>,
+    /// Categories such as the ECU that sends it; the first is its group.
+    #[serde(default)]
+    tags: Vec<String>,
+    /// The moving average of the rate as of the latest frame.
+    #[serde(skip)]
+    smoothed_rate: Option<(f64, SystemTime)>,
+    #[serde(skip)]
+    sequence: Option<Sequence>,
 }
 
 #[serde_as]
-#[derive(Serialize)]
+#[derive(Clone, Serialize, Deserialize)]
 struct Value {
+    kind: FrameKind,
+    dlc: usize,
     data: String,
     #[serde_as(as = "TimestampMilliSeconds")]
     ts: SystemTime,
 }
 
 impl Value {
-    fn bg_color(&self) -> Color {
-        match self.ts.elapsed() {
-            Ok(d) if d.as_secs() < 1 => Color::Rgb(255, 155, 53),
-            Ok(d) if d.as_secs() < 2 => Color::Rgb(189, 55, 10),
-            Ok(d) if d.as_secs() < 3 => Color::Rgb(94, 0, 0),
-            _ => Color::Black,
-        }
+    fn bg_color(&self, theme: &theme::Theme) -> Color {
+        // A frame stamped in the future means the clock stepped backwards;
+        // treat it as brand new rather than letting the row go dark.
+        let age = self.ts.elapsed().unwrap_or_default();
+        theme.fade.color(age).unwrap_or(theme.background)
     }
 
-    fn diff(&self, other: Option<&Self>) -> Cell {
+    fn diff(
+        &self,
+        other: Option<&Self>,
+        view: &View,
+        ignored_bytes: &BTreeSet<usize>,
+        constant: &[bool],
+        stale: bool,
+        width: u16,
+    ) -> Cell<'_> {
+        let (mode, theme) = (view.mode, &view.theme);
         let mut diff = Line::default();
-        for i in 0..self.data.len() {
-            let c = self.data.get(i..i + 1).unwrap();
-            let color = if let Some(other) = other {
-                if other.data.get(i..i + 1) == Some(c) {
-                    Color::White
-                } else {
-                    Color::LightCyan
+        if stale {
+            diff.spans.push(Span::styled("STALE ", theme.badge));
+        }
+        if self.kind == FrameKind::Remote {
+            diff.spans.push(Span::styled("RTR", theme.badge));
+        }
+        let mut theirs = other.map(|other| hex_pairs(&other.data));
+        for (i, byte) in hex_pairs(&self.data).enumerate() {
+            let style = match theirs.as_mut().map(|theirs| theirs.next()) {
+                Some(prev) if prev != Some(byte) && !ignored_bytes.contains(&i) => {
+                    let values = prev
+                        .map(|prev| (u8::from_str_radix(prev, 16), u8::from_str_radix(byte, 16)));
+                    match values {
+                        Some((Ok(prev), Ok(value))) if view.diff_direction => {
+                            match value.cmp(&prev) {
+                                Ordering::Greater => theme.increased,
+                                Ordering::Less => theme.decreased,
+                                Ordering::Equal => theme.changed,
+                            }
+                        }
+                        _ => theme.changed,
+                    }
                 }
-            } else {
-                Color::White
+                _ if constant.get(i) == Some(&true) => theme.constant,
+                _ => theme.text,
             };
-            diff.spans.push(Span::styled(c, Style::default().fg(color)))
+            if i > 0 {
+                diff.spans.push(mode.separator().into());
+            }
+            diff.spans.push(Span::styled(mode.render(byte), style))
         }
-        diff.patch_style(Style::default().bg(self.bg_color()));
+        truncate(&mut diff, width.into());
+        diff.patch_style(Style::default().bg(self.bg_color(theme)));
         diff.into()
     }
 }
 
+/// Cuts `line` to `width` characters, ending it in an ellipsis if anything
+/// was cut so a clipped payload doesn't pass for a whole one.
+fn truncate(line: &mut Line<'_>, width: usize) {
+    if line.width() <= width {
+        return;
+    }
+    let mut left = width.saturating_sub(1);
+    for span in &mut line.spans {
+        let len = span.content.chars().count();
+        if len > left {
+            span.content = span.content.chars().take(left).collect::<String>().into();
+        }
+        left -= len.min(left);
+    }
+    line.spans.retain(|span| !span.content.is_empty());
+    line.spans.push("…".into());
+}
+
+/// Splits a payload into the two-character chunks that make up each byte.
+/// Chunks are cut on char boundaries, so malformed input never panics and a
+/// trailing odd character becomes a chunk of its own.
+fn hex_pairs(data: &str) -> impl Iterator<Item = &str> {
+    let mut rest = data;
+    iter::from_fn(move || {
+        let end = rest.char_indices().nth(2).map_or(rest.len(), |(i, _)| i);
+        let (byte, tail) = rest.split_at(end);
+        rest = tail;
+        (!byte.is_empty()).then_some(byte)
+    })
+}
+
+impl Value {
+    /// The payload as bytes, skipping any malformed pairs.
+    fn bytes(&self) -> Vec<u8> {
+        hex_pairs(&self.data)
+            .filter_map(|byte| u8::from_str_radix(byte, 16).ok())
+            .collect()
+    }
+}
+
 impl From<canbus::Message> for Value {
     fn from(msg: canbus::Message) -> Self {
         Self {
+            kind: msg.kind,
+            dlc: msg.dlc,
             data: msg.data,
             ts: msg.ts,
         }
@@ -66,134 +532,2250 @@ impl From<canbus::Message> for Value {
 }
 
 impl Message {
-    fn merge(&mut self, other: canbus::Message) {
-        self.values.push(other.into());
+    fn merge(&mut self, other: canbus::Message, history: usize) {
+        self.count += 1;
+        if self.values.len() >= history {
+            self.values.pop_front();
+        }
+        self.values.push_back(other.into());
+        if let Some(sequence) = &mut self.sequence {
+            sequence.feed(&self.values.back().unwrap().bytes());
+        }
+    }
+
+    fn dlc(&self, theme: &theme::Theme) -> Cell<'_> {
+        let mut latest = self.values.iter().rev();
+        let dlc = latest.next().unwrap().dlc;
+        let style = match latest.next() {
+            Some(prev) if prev.dlc != dlc => theme.changed,
+            _ => theme.text,
+        };
+        Cell::from(dlc.to_string()).style(style)
+    }
+
+    /// Adds the latest frame to the moving average of the rate: each frame
+    /// counts 1/τ and every count decays by e^(-t/τ), which averages out to
+    /// the frame rate.
+    fn smooth_rate(&mut self, tau: Duration) {
+        if tau.is_zero() {
+            return;
+        }
+        let ts = self.values.back().unwrap().ts;
+        let decayed = self.smoothed_rate.map_or(0.0, |(rate, at)| {
+            let dt = ts.duration_since(at).unwrap_or_default();
+            rate * (-dt.as_secs_f64() / tau.as_secs_f64()).exp()
+        });
+        self.smoothed_rate = Some((decayed + 1.0 / tau.as_secs_f64(), ts));
+    }
+
+    /// The rate as shown: the moving average decayed to now, or the raw
+    /// rate without smoothing.
+    fn rate(&self, view: &View) -> f64 {
+        match self.smoothed_rate {
+            Some((rate, at)) if !view.rate_smoothing.is_zero() => {
+                let dt = at.elapsed().unwrap_or_default();
+                rate * (-dt.as_secs_f64() / view.rate_smoothing.as_secs_f64()).exp()
+            }
+            _ => self.rate_hz(),
+        }
+    }
+
+    /// Frames in the last second.
+    fn rate_hz(&self) -> f64 {
+        self.values
+            .iter()
+            .rev()
+            .take_while(|v| v.ts.elapsed().is_ok_and(|d| d.as_secs() < 1))
+            .count() as f64
+    }
+
+    /// Gaps between consecutive frames in the stored history.
+    fn deltas(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.values
+            .iter()
+            .zip(self.values.iter().skip(1))
+            .map(|(a, b)| b.ts.duration_since(a.ts).unwrap_or_default())
+    }
+
+    fn definition<'a>(&self, dbc: Option<&'a dbc::Database>) -> Option<&'a dbc::MessageDef> {
+        dbc?.get(self.id_value?)
+    }
+
+    fn detail(
+        &self,
+        dbc: Option<&dbc::Database>,
+        view: &View,
+        byte: usize,
+        watch: Watch,
+    ) -> Paragraph<'_> {
+        let mode = view.mode;
+        let deltas: Vec<_> = self.deltas().collect();
+        let timing = match (deltas.last(), deltas.iter().min(), deltas.iter().max()) {
+            (Some(last), Some(min), Some(max)) => {
+                let avg = deltas.iter().sum::<Duration>() / deltas.len() as u32;
+                format!(
+                    "delta last {} | min {} | avg {} | max {} | over {} frames",
+                    millis(*last),
+                    millis(*min),
+                    millis(avg),
+                    millis(*max),
+                    self.values.len()
+                )
+            }
+            _ => "delta: waiting for a second frame".to_string(),
+        };
+        // The table may clip long payloads, so show the whole latest one.
+        let payload: Vec<_> = hex_pairs(&self.values.back().unwrap().data)
+            .map(|byte| mode.render(byte))
+            .collect();
+        let payloads: Vec<_> = self.values.iter().map(Value::bytes).collect();
+        let guesses: Vec<_> = checksum::rank(&payloads)
+            .into_iter()
+            .map(|(name, rate)| format!("{name} {:.0}%", rate * 100.0))
+            .collect();
+        let mut lines = vec![
+            Line::from(format!(
+                "first seen {}, last seen {}",
+                view.time(self.first_seen),
+                view.time(self.values.back().unwrap().ts)
+            )),
+            Line::from(timing),
+            Line::from(format!(
+                "rate {:.0}/s over the last second, {:.1}/s shown",
+                self.rate_hz(),
+                self.rate(view)
+            )),
+            Line::from(format!("payload {}", payload.join(mode.separator()))),
+            Line::from(match guesses.is_empty() {
+                true => "last byte as checksum: no match".to_string(),
+                false => format!("last byte as checksum: {}", guesses.join(", ")),
+            }),
+            Line::from(
+                match watch.raw(&self.values.back().unwrap().bytes(), byte) {
+                    Some(raw) => format!(
+                        "{}: {} as {watch}",
+                        bytes_label(byte, watch),
+                        watch.value(raw)
+                    ),
+                    None => format!("{}: past the end of the payload", bytes_label(byte, watch)),
+                },
+            ),
+        ];
+        let mut title = format!("ID {}", self.id);
+        if !self.source.is_empty() {
+            title += &format!(" on {}", self.source);
+        }
+        if let Some(def) = self.definition(dbc) {
+            title += &format!(" {}", def.name);
+        }
+        if let Some(note) = &self.note {
+            title += &format!(" \"{note}\"");
+        }
+        if !self.tags.is_empty() {
+            title += &format!(" [{}]", self.tags.join(", "));
+        }
+        title += &format!(" | {}", bytes_label(byte, watch));
+        if let Some(baseline) = &self.baseline {
+            title += &format!(" | diff against {}", baseline.data);
+        }
+        if !self.ignored_bytes.is_empty() {
+            let bytes: Vec<_> = self.ignored_bytes.iter().map(usize::to_string).collect();
+            title += &format!(" | diff ignores {}", bytes.join(", "));
+        }
+        if let Some(sequence) = &self.sequence {
+            lines.push(Line::from(match sequence.dropped {
+                0 => format!("byte {} counter: no gaps", sequence.byte),
+                1 => format!("byte {} counter: 1 frame dropped", sequence.byte),
+                n => format!("byte {} counter: {n} frames dropped", sequence.byte),
+            }));
+        }
+        if let Some(def) = self.definition(dbc) {
+            let data = self.values.back().unwrap().bytes();
+            lines.extend(def.signals.iter().map(|signal| {
+                let value = match signal.decode(&data) {
+                    Some(value) => format!("{} {}", dbc::format_value(value), signal.unit),
+                    None => "-".to_string(),
+                };
+                Line::from(vec![
+                    Span::styled(format!("{}: ", signal.name), view.theme.key),
+                    Span::raw(value),
+                ])
+            }));
+        }
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::new().borders(Borders::TOP).title(title))
+    }
+
+    /// The latest payload as text, eight bytes per line, with the byte
+    /// under the cursor highlighted.
+    fn ascii(&self, byte: usize, theme: &theme::Theme) -> Paragraph<'_> {
+        let data = self.values.back().unwrap().bytes();
+        let lines: Vec<Line> = data
+            .chunks(8)
+            .enumerate()
+            .map(|(line, chunk)| {
+                Line::from(Vec::from_iter(chunk.iter().enumerate().map(|(i, &b)| {
+                    let c = if b.is_ascii_graphic() || b == b' ' {
+                        char::from(b)
+                    } else {
+                        '·'
+                    };
+                    let style = if line * 8 + i == byte {
+                        theme.cursor
+                    } else {
+                        Style::new()
+                    };
+                    Span::styled(c.to_string(), style)
+                })))
+            })
+            .collect();
+        Paragraph::new(lines).block(
+            Block::new()
+                .borders(Borders::LEFT)
+                .padding(Padding::horizontal(1))
+                .title("ASCII"),
+        )
+    }
+
+    /// The watched integer in every stored frame, as plotted; frames too
+    /// short for it count as zero.
+    fn watch_history(&self, byte: usize, watch: Watch) -> Vec<u64> {
+        self.values
+            .iter()
+            .map(|value| {
+                let raw = watch.raw(&value.bytes(), byte);
+                raw.map_or(0, |raw| watch.plotted(raw))
+            })
+            .collect()
+    }
+
+    /// The lowest, highest and latest value of each payload byte across the
+    /// history. Frames too short to have a byte don't count towards it.
+    fn byte_stats(&self) -> Vec<(u8, u8, u8)> {
+        let mut stats: Vec<(u8, u8, u8)> = Vec::new();
+        for value in &self.values {
+            for (i, b) in value.bytes().into_iter().enumerate() {
+                match stats.get_mut(i) {
+                    Some((min, max, last)) => {
+                        *min = (*min).min(b);
+                        *max = (*max).max(b);
+                        *last = b;
+                    }
+                    None => stats.push((b, b, b)),
+                }
+            }
+        }
+        stats
+    }
+
+    /// Which payload bytes hold the same value in every stored frame; none
+    /// do until there are two frames to compare.
+    fn constant_mask(&self) -> Vec<bool> {
+        if self.values.len() < 2 {
+            return Vec::new();
+        }
+        self.byte_stats()
+            .into_iter()
+            .map(|(min, max, _)| min == max)
+            .collect()
+    }
+
+    /// [`Message::byte_stats`] as a table, one row per byte, with the byte
+    /// under the cursor highlighted.
+    fn stats(&self, mode: DisplayMode, byte: usize, theme: &theme::Theme) -> Table<'_> {
+        let render = |b: u8| mode.render(&format!("{b:02X}")).into_owned();
+        let rows = self
+            .byte_stats()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (min, max, last))| {
+                let row = Row::new([i.to_string(), render(min), render(max), render(last)]);
+                if i == byte {
+                    row.style(theme.cursor)
+                } else {
+                    row
+                }
+            });
+        Table::new(rows, [Constraint::Length(4); 4])
+            .header(Row::new(["byte", "min", "max", "last"]).style(theme.header))
+            .block(
+                Block::new()
+                    .borders(Borders::TOP)
+                    .title(format!("bytes over the last {} frames", self.values.len())),
+            )
+    }
+
+    /// The latest payload bit by bit, a line per byte with the most
+    /// significant bit first. Bits that differ from the previous frame are
+    /// highlighted and so is the chosen byte's label.
+    fn bits(&self, byte: usize, theme: &theme::Theme) -> Paragraph<'_> {
+        let mut latest = self.values.iter().rev();
+        let data = latest.next().unwrap().bytes();
+        let prev = latest.next().map(Value::bytes);
+        let mut lines = vec![Line::from("        7 6 5 4 3 2 1 0")];
+        lines.extend(data.iter().enumerate().map(|(i, &b)| {
+            let label = format!("byte {i:<2}");
+            let mut spans = vec![if i == byte {
+                Span::styled(label, theme.cursor)
+            } else {
+                Span::raw(label)
+            }];
+            for bit in (0..8).rev() {
+                let set = b >> bit & 1;
+                let changed = prev
+                    .as_ref()
+                    .and_then(|prev| prev.get(i))
+                    .is_some_and(|p| p >> bit & 1 != set);
+                let style = if changed { theme.changed } else { theme.text };
+                spans.push(" ".into());
+                spans.push(Span::styled(set.to_string(), style));
+            }
+            Line::from(spans)
+        }));
+        Paragraph::new(lines).block(Block::new().borders(Borders::TOP).title("bits"))
     }
 
-    fn as_row(&self, cols: usize) -> Row {
-        let row = Row::new(
-            iter::once(self.id.as_str().into())
-                .chain(
+    /// The group the row is shown in when grouping.
+    fn group(&self) -> &str {
+        self.tags.first().map_or(UNTAGGED, String::as_str)
+    }
+
+    /// Text for the ID column: the DBC message name if `names` has one.
+    fn label<'a>(&'a self, names: Option<&'a HashMap<u32, String>>) -> &'a str {
+        names
+            .and_then(|names| names.get(&self.id_value?))
+            .map_or(&self.id, String::as_str)
+    }
+
+    fn as_row<'a>(
+        &'a self,
+        cols: usize,
+        width: u16,
+        view: &View,
+        names: Option<&'a HashMap<u32, String>>,
+        rules: &rules::Rules,
+        marked: bool,
+    ) -> Row<'a> {
+        let label = self.label(names);
+        let label = if marked {
+            Cell::from(format!("*{label}")).bold()
+        } else {
+            label.into()
+        };
+        let mut cells = vec![label];
+        if view.show_source {
+            cells.push(self.source.as_str().into());
+        }
+        cells.push(self.dlc(&view.theme));
+        if view.show_rate {
+            cells.push(
+                Line::from(format!("{:.0}", self.rate(view)))
+                    .alignment(Alignment::Right)
+                    .into(),
+            );
+        }
+        if view.show_time {
+            cells.push(view.time(self.values.back().unwrap().ts).into());
+        }
+        if view.show_count {
+            cells.push(
+                Line::from(self.count.to_string())
+                    .alignment(Alignment::Right)
+                    .into(),
+            );
+        }
+        let constant = self.constant_mask();
+        cells.extend(
+            self.values
+                .iter()
+                .rev()
+                .zip(
                     self.values
                         .iter()
                         .rev()
-                        .zip(
-                            self.values
-                                .iter()
-                                .rev()
-                                .skip(1)
-                                .map(Some)
-                                .chain(iter::repeat(None)),
-                        )
-                        .map(|(a, b)| a.diff(b))
-                        .take(cols),
+                        .skip(1)
+                        .map(Some)
+                        .chain(iter::repeat(None)),
                 )
-                .collect::<Vec<Cell>>(),
+                .enumerate()
+                .map(|(i, (a, b))| {
+                    let stale = i == 0 && view.is_stale(self);
+                    a.diff(
+                        self.baseline.as_ref().or(b),
+                        view,
+                        &self.ignored_bytes,
+                        &constant,
+                        stale,
+                        width,
+                    )
+                })
+                .take(cols),
         );
+        let row = Row::new(cells);
+        let latest = self.values.back().unwrap();
         if self.ignored {
-            row.dark_gray().crossed_out()
+            row.style(view.theme.ignored)
+        } else if let Some(color) = rules.color(&self.id, &latest.bytes()) {
+            row.style(view.theme.rule(color))
+        } else if self.is_error {
+            row.style(view.theme.error_frame)
         } else {
             row
         }
     }
 }
 
+fn unix_epoch() -> SystemTime {
+    SystemTime::UNIX_EPOCH
+}
+
 impl From<canbus::Message> for Message {
     fn from(other: canbus::Message) -> Self {
         Self {
+            id_value: canbus::parse_id(&other.id),
             id: other.id.clone(),
-            values: vec![other.into()],
+            source: other.source.clone(),
+            is_extended: other.is_extended,
+            is_error: other.kind == FrameKind::Error,
+            first_seen: other.ts,
+            values: VecDeque::from([other.into()]),
             ignored: false,
             pinned: false,
+            ignored_bytes: BTreeSet::new(),
+            count: 1,
+            baseline: None,
+            note: None,
+            tags: Vec::new(),
+            smoothed_rate: None,
+            sequence: None,
+        }
+    }
+}
+
+/// Estimated share of the bus used over the last second, in percent. Frame
+/// sizes count every protocol field but not stuff bits, so this reads
+/// slightly low on busy buses.
+fn bus_load(msgs: &[Message], bitrate: u32) -> f64 {
+    let bits: usize = msgs
+        .iter()
+        .map(|msg| {
+            let overhead = if msg.is_extended { 67 } else { 47 };
+            msg.values
+                .iter()
+                .rev()
+                .take_while(|v| v.ts.elapsed().is_ok_and(|d| d.as_secs() < 1))
+                .map(|v| overhead + v.data.len() / 2 * 8)
+                .sum::<usize>()
+        })
+        .sum();
+    bits as f64 * 100.0 / f64::from(bitrate)
+}
+
+/// Width of a value cell that fits the longest payload of the rows in
+/// `order`: their latest one in compact view, or any in their history.
+fn value_width(msgs: &[Message], order: &[usize], view: &View) -> u16 {
+    let longest = order
+        .iter()
+        .flat_map(|&i| {
+            let values = &msgs[i].values;
+            let shown = if view.compact { values.len() - 1 } else { 0 };
+            values.range(shown..)
+        })
+        .map(|value| value.data.len().div_ceil(2))
+        .max()
+        .unwrap_or(8);
+    view.mode.width(longest)
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.3} ms", d.as_secs_f64() * 1000.0)
+}
+
+fn next_row(row: usize, len: usize) -> Option<usize> {
+    (len > 0).then(|| (row + 1) % len)
+}
+
+fn prev_row(row: usize, len: usize) -> Option<usize> {
+    (len > 0).then(|| (row + len - 1) % len)
+}
+
+/// The chosen bytes, e.g. `byte 2` or `bytes 2-3`.
+fn bytes_label(byte: usize, watch: Watch) -> String {
+    match watch.len {
+        1 => format!("byte {byte}"),
+        len => format!("bytes {byte}-{}", byte + len - 1),
+    }
+}
+
+/// Cursor or scroll position after a paging key, or `None` for other keys.
+fn paged(code: crossterm::event::KeyCode, pos: usize, page: usize, last: usize) -> Option<usize> {
+    match code {
+        crossterm::event::KeyCode::PageDown => Some((pos + page).min(last)),
+        crossterm::event::KeyCode::PageUp => Some(pos.saturating_sub(page)),
+        crossterm::event::KeyCode::Home => Some(0),
+        crossterm::event::KeyCode::End => Some(last),
+        _ => None,
+    }
+}
+
+/// Puts `text` on the clipboard with an OSC 52 escape, which the terminal
+/// handles, so it also works over SSH. Terminals without support ignore it.
+fn copy_to_clipboard(text: &str) -> std::io::Result<()> {
+    use base64::Engine;
+    let mut stdout = std::io::stdout();
+    write!(
+        stdout,
+        "\x1b]52;c;{}\x07",
+        base64::engine::general_purpose::STANDARD.encode(text)
+    )?;
+    stdout.flush()
+}
+
+fn help(theme: &theme::Theme) -> Paragraph<'static> {
+    let key_width = HELP
+        .iter()
+        .map(|(key, _)| key.len())
+        .max()
+        .unwrap_or_default();
+    let lines: Vec<Line> = HELP
+        .iter()
+        .map(|(key, action)| {
+            Line::from(vec![
+                Span::styled(format!("{key:>key_width$}  "), theme.key),
+                Span::raw(*action),
+            ])
+        })
+        .collect();
+    Paragraph::new(lines).block(
+        Block::new()
+            .borders(Borders::ALL)
+            .title("keys")
+            .title(Title::from("any key to close").position(Position::Bottom)),
+    )
+}
+
+/// The last lines that weren't frames, escaped so control characters and
+/// garbage from a wrong baud rate stay visible.
+fn bad_lines_list(lines: &VecDeque<String>) -> Paragraph<'_> {
+    let text: Vec<Line> = if lines.is_empty() {
+        vec![Line::from("no bad lines")]
+    } else {
+        lines
+            .iter()
+            .map(|line| Line::from(line.escape_debug().to_string()))
+            .collect()
+    };
+    Paragraph::new(text).block(
+        Block::new()
+            .borders(Borders::ALL)
+            .title("bad lines")
+            .title(Title::from("any key to close").position(Position::Bottom)),
+    )
+}
+
+/// Raw input lines with unprintable bytes escaped, so line endings and
+/// garbage from a wrong baud rate show up as they are.
+fn raw_panel(lines: &VecDeque<Vec<u8>>) -> Paragraph<'_> {
+    let text: Vec<Line> = lines
+        .iter()
+        .map(|line| {
+            let escaped: String = line
+                .iter()
+                .flat_map(|&b| std::ascii::escape_default(b))
+                .map(char::from)
+                .collect();
+            Line::from(escaped)
+        })
+        .collect();
+    Paragraph::new(text).block(
+        Block::new()
+            .borders(Borders::TOP)
+            .title(format!("raw input, last {RAW_LINES} lines")),
+    )
+}
+
+/// The cyclic transmit entries, with the one at `selected` highlighted.
+fn cyclic_panel(
+    entries: &[cyclic::Entry],
+    selected: usize,
+    theme: &theme::Theme,
+) -> Paragraph<'static> {
+    let lines: Vec<Line> = if entries.is_empty() {
+        vec![Line::from("no cyclic frames yet, A) adds one")]
+    } else {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let text = format!(
+                    "{:<28} every {} ms{}",
+                    entry.frame.to_string(),
+                    entry.period.as_millis(),
+                    if entry.paused { ", paused" } else { "" }
+                );
+                match i == selected {
+                    true => Line::styled(text, theme.selection),
+                    false => Line::from(text),
+                }
+            })
+            .collect()
+    };
+    Paragraph::new(lines).block(
+        Block::new()
+            .borders(Borders::ALL)
+            .title("cyclic transmit")
+            .title(
+                Title::from("a) add; e) edit; Space) pause; x) remove; Esc) close")
+                    .position(Position::Bottom),
+            ),
+    )
+}
+
+/// The ignore patterns, with the one at `selected` highlighted.
+fn patterns_panel(
+    patterns: &[canbus::IdPattern],
+    selected: usize,
+    theme: &theme::Theme,
+) -> Paragraph<'static> {
+    let lines: Vec<Line> = if patterns.is_empty() {
+        vec![Line::from("no ignore patterns yet, a) adds one")]
+    } else {
+        patterns
+            .iter()
+            .enumerate()
+            .map(|(i, pattern)| match i == selected {
+                true => Line::styled(pattern.to_string(), theme.selection),
+                false => Line::from(pattern.to_string()),
+            })
+            .collect()
+    };
+    Paragraph::new(lines).block(
+        Block::new()
+            .borders(Borders::ALL)
+            .title("ignore patterns")
+            .title(Title::from("a) add; x) remove; Esc) close").position(Position::Bottom)),
+    )
+}
+
+/// Interleaves group headers with the rows of a grouped table, whose
+/// groups are given per row. Collapsed groups, with their sizes, only get a
+/// header. Also returns where each row ended up.
+fn with_headers<'a>(
+    rows: Vec<Row<'a>>,
+    groups: &[&str],
+    collapsed: &BTreeMap<&str, usize>,
+    theme: &theme::Theme,
+) -> (Vec<Row<'a>>, Vec<usize>) {
+    let header = |group: &str, size: Option<usize>| {
+        Row::new([match size {
+            Some(size) => format!("▸ {group} ({size})"),
+            None => format!("▾ {group}"),
+        }])
+        .style(theme.header)
+    };
+    let mut pending: Vec<_> = collapsed.iter().collect();
+    pending.sort_by_key(|(group, _)| group_key(group));
+    let mut pending = VecDeque::from(pending);
+    let mut table = Vec::new();
+    let mut positions = Vec::new();
+    let mut previous = None;
+    for (row, &group) in rows.into_iter().zip(groups) {
+        while let Some(&(collapsed, size)) = pending.front() {
+            if group_key(collapsed) >= group_key(group) {
+                break;
+            }
+            table.push(header(collapsed, Some(*size)));
+            pending.pop_front();
+        }
+        // Rows added while filtering sit at the bottom, out of their group,
+        // and get a header of their own.
+        if previous != Some(group) {
+            table.push(header(group, None));
+            previous = Some(group);
+        }
+        positions.push(table.len());
+        table.push(row);
+    }
+    table.extend(
+        pending
+            .into_iter()
+            .map(|(group, size)| header(group, Some(*size))),
+    );
+    (table, positions)
+}
+
+/// Groups sort by name, with untagged rows last.
+fn group_key(group: &str) -> (bool, &str) {
+    (group == UNTAGGED, group)
+}
+
+/// A `width` by `height` rectangle centered in `area`, clipped to fit.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn source(args: &cli::Args) -> Result<Box<dyn Source>> {
+    if let Some(log) = &args.replay {
+        if !log.exists() {
+            bail!("replay file {} does not exist", log.display());
+        }
+        Ok(Box::new(canbus::ReplaySource {
+            path: log.clone(),
+            speed: args.replay_speed,
+        }))
+    } else if let Some(interface) = &args.interface {
+        socketcan_source(interface)
+    } else if args.device == Path::new("-") {
+        Ok(Box::new(canbus::StdinSource {
+            format: args.format,
+        }))
+    } else {
+        if !args.device.exists() {
+            bail!("device {} does not exist", args.device.display());
+        }
+        if matches!(args.format, canbus::Format::Slcan)
+            && canbus::slcan_bitrate(args.bitrate).is_none()
+        {
+            bail!(
+                "slcan adapters only support bitrates of 10000, 20000, 50000, 100000, \
+                 125000, 250000, 500000, 800000 and 1000000"
+            );
         }
+        Ok(Box::new(canbus::SerialSource {
+            path: args.device.clone(),
+            settings: args.serial,
+            format: args.format,
+            bitrate: args.bitrate,
+            reconnect_interval: args.reconnect_interval,
+        }))
     }
 }
 
+#[cfg(target_os = "linux")]
+fn socketcan_source(interface: &str) -> Result<Box<dyn Source>> {
+    let source = socketcan::SocketCanSource::open(interface)
+        .with_context(|| format!("cannot open SocketCAN interface {interface}"))?;
+    Ok(Box::new(source))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn socketcan_source(_interface: &str) -> Result<Box<dyn Source>> {
+    bail!("SocketCAN interfaces are only supported on Linux")
+}
+
 fn main() -> Result<()> {
-    crossterm::terminal::enable_raw_mode()?;
-    std::io::stdout().execute(crossterm::terminal::EnterAlternateScreen)?;
+    let args = cli::Args::parse()?;
+    if let Some((a, b)) = &args.diff {
+        let stdout = std::io::stdout();
+        return diff::report(a, b, &mut stdout.lock(), !theme::no_color());
+    }
+    let state_path = match (&args.state_file, args.no_persist) {
+        (_, true) => None,
+        (Some(path), false) => Some(path.clone()),
+        (None, false) => state::default_path(),
+    };
+    let mut state = match &state_path {
+        Some(path) => State::load(path)?,
+        None => State::default(),
+    };
+    let loaded = match &args.load {
+        Some(path) => export::load(path)?,
+        None => Vec::new(),
+    };
+    let dbc = args.dbc.as_deref().map(dbc::Database::load).transpose()?;
+    let sinks = Sinks {
+        logger: match &args.log {
+            Some(path) => Some(logger::Logger::create(path, args.candump_interface())?),
+            None => None,
+        },
+        mqtt: args.mqtt.clone().map(mqtt::Publisher::spawn),
+        metrics: args.metrics_addr.map(metrics::Metrics::serve).transpose()?,
+        websocket: args.ws_addr.map(websocket::Server::serve).transpose()?,
+    };
+    let rules = match &args.rules {
+        Some(path) => rules::Rules::load(path)?,
+        None => rules::Rules::default(),
+    };
+    let started = iter::once(SystemTime::now())
+        .chain(
+            loaded
+                .iter()
+                .flat_map(|msg| &msg.values)
+                .map(|value| value.ts),
+        )
+        .min()
+        .unwrap();
+    let bus = match args.load {
+        Some(_) => Bus {
+            rx: canbus::spawn(|_| {}),
+            transmitter: None,
+            started,
+        },
+        None => {
+            let source = source(&args)?;
+            Bus {
+                transmitter: source.transmitter(),
+                rx: source.messages(),
+                started,
+            }
+        }
+    };
+
+    let result = if args.stream || args.duration.is_some() || !args.wait_for.is_empty() {
+        Ok(collect(&bus, &args, &state, loaded, &sinks))
+    } else {
+        // Put the terminal back before the panic message is printed,
+        // otherwise it lands on the alternate screen and the shell is left
+        // in raw mode.
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let _ = restore_terminal();
+            hook(info);
+        }));
+        crossterm::terminal::enable_raw_mode()?;
+        std::io::stdout().execute(crossterm::terminal::EnterAlternateScreen)?;
+        let result = run(bus, &args, &mut state, loaded, dbc.as_ref(), &rules, &sinks);
+        restore_terminal()?;
+        result
+    };
+    if let Some(logger) = sinks.logger {
+        if let Err(e) = logger.finish() {
+            eprintln!("canalyzer: {e:#}");
+        }
+    }
+    let mut msgs = result?;
+    if let Some(path) = &state_path {
+        for msg in &msgs {
+            state.update(
+                &msg.id,
+                msg.ignored,
+                msg.pinned,
+                msg.note.as_deref(),
+                &msg.tags,
+            );
+        }
+        if let Err(e) = state.save(path) {
+            eprintln!("canalyzer: cannot save state to {}: {e}", path.display());
+        }
+    }
+    if args.relative_time {
+        msgs = export::rebased(&msgs, started);
+    }
+    if let Some(path) = &args.export_csv {
+        export::csv(path, &msgs)?;
+    }
+    if let Some(path) = &args.export_candump {
+        export::candump(path, args.candump_interface(), &msgs)?;
+    }
+    if let Some(path) = &args.export_pcap {
+        export::pcapng(path, &msgs)?;
+    }
+    match &args.output {
+        Some(path) => export::json(path, &msgs)?,
+        None if args.stream => {}
+        None => {
+            let _ = serde_json::to_writer(std::io::stdout(), &msgs);
+            println!();
+        }
+    }
+    let missing: Vec<_> = args
+        .wait_for
+        .iter()
+        .filter(|&&id| msgs.iter().all(|msg| msg.id_value != Some(id)))
+        .map(|id| format!("{id:X}"))
+        .collect();
+    if !missing.is_empty() {
+        bail!("never saw {}", missing.join(", "));
+    }
+    Ok(())
+}
+
+/// Adds a frame to its ID's row, or a new row that starts out ignored or
+/// pinned as `persisted` says, and returns the row's index.
+fn ingest(
+    msgs: &mut Vec<Message>,
+    index: &mut Index,
+    frame: canbus::Message,
+    args: &cli::Args,
+    persisted: &State,
+) -> usize {
+    let key = row_key(
+        &frame.source,
+        &frame.id,
+        frame.is_extended,
+        frame.kind == FrameKind::Error,
+    );
+    match index.get(&key) {
+        Some(&i) => {
+            msgs[i].merge(frame, args.history);
+            msgs[i].smooth_rate(args.rate_smoothing);
+            i
+        }
+        None => {
+            let mut msg = Message::from(frame);
+            msg.ignored = persisted.ignores(&msg.id);
+            msg.pinned = persisted.pins(&msg.id);
+            msg.note = persisted.note(&msg.id).cloned();
+            msg.tags = persisted.tags(&msg.id).to_vec();
+            msg.smooth_rate(args.rate_smoothing);
+            msgs.push(msg);
+            index.insert(key, msgs.len() - 1);
+            msgs.len() - 1
+        }
+    }
+}
+
+/// Position in `msgs` of each row, so frames are merged without a scan.
+type Index = HashMap<RowKey, usize>;
+
+/// What a row's frames have in common: the source, the ID, whether it is
+/// extended and whether they are error frames.
+type RowKey = (String, String, bool, bool);
+
+/// IDs are compared by value rather than as written, so `0a1`, `0A1` and
+/// `00A1` share a row, and so do a canalyzer-format `0101` and the `101`
+/// its candump export reads back as.
+fn row_key(source: &str, id: &str, is_extended: bool, is_error: bool) -> RowKey {
+    (
+        source.to_string(),
+        canbus::key_id(id),
+        is_extended,
+        is_error,
+    )
+}
+
+fn index(msgs: &[Message]) -> Index {
+    msgs.iter()
+        .enumerate()
+        .map(|(i, msg)| {
+            let key = row_key(&msg.source, &msg.id, msg.is_extended, msg.is_error);
+            (key, i)
+        })
+        .collect()
+}
+
+/// Collects frames without the TUI until `--duration` has passed, every
+/// `--wait-for` ID has been seen or the source ends, whichever comes first.
+/// With `--stream` each frame is also printed as a line of JSON as it
+/// arrives, and collection ends early if stdout is closed.
+fn collect(
+    bus: &Bus,
+    args: &cli::Args,
+    persisted: &State,
+    mut msgs: Vec<Message>,
+    sinks: &Sinks,
+) -> Vec<Message> {
+    let mut index = index(&msgs);
+    let deadline = args.duration.map(|duration| Instant::now() + duration);
+    let waiting = |msgs: &[Message]| {
+        args.wait_for
+            .iter()
+            .any(|&id| msgs.iter().all(|msg| msg.id_value != Some(id)))
+    };
+    while args.wait_for.is_empty() || waiting(&msgs) {
+        let event = match deadline {
+            Some(deadline) => {
+                match bus
+                    .rx
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                {
+                    Ok(event) => event,
+                    Err(_) => break,
+                }
+            }
+            None => match bus.rx.recv() {
+                Ok(event) => event,
+                Err(_) => break,
+            },
+        };
+        match event {
+            canbus::Event::Frame(m) => {
+                sinks.send(&m);
+                let i = ingest(&mut msgs, &mut index, m, args, persisted);
+                sinks.ingested(&msgs[i]);
+                if args.stream {
+                    let msg = &msgs[i];
+                    let mut value = msg.values.back().unwrap().clone();
+                    if args.relative_time {
+                        value.ts = export::rebase(value.ts, bus.started);
+                    }
+                    let mut out = std::io::stdout().lock();
+                    let written =
+                        export::json_line(&mut out, msg, &value).and_then(|()| out.flush());
+                    if written.is_err() {
+                        break;
+                    }
+                }
+            }
+            canbus::Event::Error(e) => eprintln!("canalyzer: {e}"),
+            canbus::Event::Raw(_) | canbus::Event::Malformed(_) | canbus::Event::Connection(_) => {}
+        }
+        for e in sinks.errors() {
+            eprintln!("canalyzer: {e}");
+        }
+    }
+    msgs
+}
+
+/// Everything frames are forwarded to as they arrive, paused or not, and
+/// the metrics kept on the rows they are added to.
+struct Sinks {
+    logger: Option<logger::Logger>,
+    mqtt: Option<mqtt::Publisher>,
+    metrics: Option<metrics::Metrics>,
+    websocket: Option<websocket::Server>,
+}
+
+impl Sinks {
+    fn send(&self, frame: &canbus::Message) {
+        if let Some(logger) = &self.logger {
+            logger.log(frame);
+        }
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish(frame);
+        }
+        if let Some(websocket) = &self.websocket {
+            websocket.broadcast(frame);
+        }
+    }
+
+    /// Called with a row once a frame has been added to it.
+    fn ingested(&self, msg: &Message) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record(msg);
+        }
+    }
+
+    /// Errors the sinks ran into since the last call.
+    fn errors(&self) -> Vec<String> {
+        self.mqtt.iter().flat_map(mqtt::Publisher::errors).collect()
+    }
+}
 
+/// Where frames come from and, on a live bus, can be sent to.
+struct Bus {
+    rx: Receiver<canbus::Event>,
+    transmitter: Option<Box<dyn canbus::Transmit>>,
+    /// When the capture started: now, or at the first frame of a loaded
+    /// session.
+    started: SystemTime,
+}
+
+fn restore_terminal() -> std::io::Result<()> {
+    std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
+    crossterm::terminal::disable_raw_mode()
+}
+
+fn run(
+    bus: Bus,
+    args: &cli::Args,
+    persisted: &mut State,
+    mut msgs: Vec<Message>,
+    dbc: Option<&dbc::Database>,
+    rules: &rules::Rules,
+    sinks: &Sinks,
+) -> Result<Vec<Message>> {
+    let Bus {
+        rx,
+        transmitter,
+        started: start,
+    } = bus;
+    // Shared with the cyclic transmit thread.
+    let transmitter = transmitter.map(|transmitter| Arc::new(Mutex::new(transmitter)));
+    let cyclic = transmitter.clone().map(cyclic::Cyclic::spawn);
     let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
-    let rx = canbus::recv();
-    let mut msgs = Vec::<Message>::new();
+    // Messages stay in arrival order; `order` holds their display order and
+    // is only rebuilt when something that affects sorting changes.
+    let mut order = Vec::<usize>::new();
+    let mut index = index(&msgs);
+    let mut dirty = !msgs.is_empty();
     let mut state = TableState::default();
+    let mut error = None;
+    let mut notice = None::<String>;
+    let mut connection = None;
+    // Monochrome is only a fallback, so it never replaces the saved theme.
+    let no_color = args.theme.is_none() && theme::no_color();
+    let mut theme = match &args.theme {
+        Some(theme) => theme.clone(),
+        None if no_color => theme::Theme::monochrome(),
+        None => persisted
+            .theme
+            .as_deref()
+            .and_then(|name| name.parse().ok())
+            .unwrap_or_default(),
+    };
+    if let Some(fade) = &args.fade {
+        theme.fade = fade.clone();
+    }
+    let mut view = View {
+        theme,
+        stale_timeout: args.stale_timeout,
+        sink_stale: args.sink_stale,
+        diff_direction: args.diff_direction,
+        rate_smoothing: args.rate_smoothing,
+        max_cols: args.max_history_cols,
+        relative_to: args.relative_time.then_some(start),
+        ..persisted.view.take().unwrap_or_default()
+    };
+    let mut filter = Filter {
+        regex: args.filter.clone(),
+        id_range: args.id_range,
+        ..Filter::default()
+    };
+    let mut prompt = None;
+    let mut input = String::new();
+    let mut input_error = None;
+    let mut paused = false;
+    // IDs marked in filter mode for bulk ignore and pin.
+    let mut marked = HashSet::<String>::new();
+    // Byte position in the selected row that M toggles, and the integer
+    // read from there on.
+    let mut byte = 0;
+    let mut watch = Watch::default();
+    let names = dbc.map(dbc::Database::names).unwrap_or_default();
+    let mut confirm_clear = false;
+    let mut triggers = args.triggers.clone();
+    // The status bar flashes until then after a trigger fires.
+    let mut flash_until = None;
+    let mut show_help = false;
+    // Input lines that weren't frames, and the last few of them for U.
+    let mut bad_lines = 0u64;
+    let mut recent_bad = VecDeque::new();
+    let mut show_bad = false;
+    // Every frame since start, including ones dropped while paused or
+    // cleared since.
+    let mut received = 0u64;
+    let started = Instant::now();
+    let mut raw_lines = VecDeque::new();
+    let mut show_raw = false;
+    // Frames sent with W, oldest first, and the one Up/Down recalled.
+    let mut sent = Vec::<String>::new();
+    let mut recall = None;
+    let mut show_cyclic = false;
+    // The entry selected in the cyclic transmit panel.
+    let mut cyclic_row = 0;
+    let mut show_patterns = false;
+    let mut pattern_row = 0;
+    // The cursor outside filter mode. It stays on its ID as rows re-sort
+    // instead of holding them in place like a selection; `i` and `p` act on
+    // it, or on the top row without one.
+    let mut hover = None::<String>;
+    // Table rows visible in the last frame, for paging.
+    let mut page = 1;
+    // Value cells that fit in the last frame, for `<` and `>`.
+    let mut fitting = 1;
+    let mut redraw = true;
+    let mut drawn = Instant::now();
 
     loop {
-        if state.selected().is_none() {
-            for m in rx.try_iter() {
-                match msgs.iter_mut().find(|existing| existing.id == m.id) {
-                    Some(existing) => existing.merge(m),
-                    None => msgs.push(m.into()),
+        // While a row is selected, frames still update their rows in place
+        // but re-sorting waits until filtering ends so rows don't move under
+        // the cursor. New IDs are appended to the bottom in the meantime.
+        let filtering = state.selected().is_some();
+        let mut handled = 0;
+        for event in rx.try_iter().take(BATCH) {
+            handled += 1;
+            redraw = true;
+            match event {
+                // Frames that arrive while paused are dropped rather than
+                // queued, so resuming doesn't replay a backlog.
+                canbus::Event::Frame(m) if paused => {
+                    received += 1;
+                    sinks.send(&m);
+                }
+                canbus::Event::Frame(m) => {
+                    received += 1;
+                    sinks.send(&m);
+                    if let Some(trigger) = triggers.iter().find(|t| t.matches(&m.id, &m.data)) {
+                        let at = chrono::DateTime::<chrono::Local>::from(m.ts);
+                        notice = Some(format!("{trigger} fired at {}", at.format("%H:%M:%S%.3f")));
+                        flash_until = Some(Instant::now() + Duration::from_secs(1));
+                        if args.bell {
+                            print!("\x07");
+                        }
+                    }
+                    let i = ingest(&mut msgs, &mut index, m, args, persisted);
+                    sinks.ingested(&msgs[i]);
+                    if filtering
+                        && filter.matches(&msgs[i])
+                        && !view.hides(&msgs[i])
+                        && !order.contains(&i)
+                    {
+                        order.push(i);
+                    }
+                    dirty |= !filtering;
+                }
+                canbus::Event::Raw(line) => {
+                    if raw_lines.len() == RAW_LINES {
+                        raw_lines.pop_front();
+                    }
+                    raw_lines.push_back(line);
+                }
+                canbus::Event::Malformed(line) => {
+                    bad_lines += 1;
+                    if recent_bad.len() == RECENT_BAD_LINES {
+                        recent_bad.pop_front();
+                    }
+                    recent_bad.push_back(line);
+                }
+                canbus::Event::Error(e) => error = Some(e),
+                canbus::Event::Connection(c) => {
+                    if let canbus::Connection::Connected = c {
+                        error = None;
+                    }
+                    connection = Some(c);
                 }
             }
         }
 
-        msgs.sort_by(|a, b| {
-            if a.ignored && !b.ignored {
-                Ordering::Greater
-            } else if !a.ignored && b.ignored {
-                Ordering::Less
-            } else if !a.pinned && b.pinned {
-                Ordering::Greater
-            } else if a.pinned && !b.pinned {
-                Ordering::Less
-            } else {
-                b.values
-                    .last()
-                    .unwrap()
-                    .ts
-                    .cmp(&a.values.last().unwrap().ts)
+        if let Some(e) = cyclic.as_ref().and_then(|cyclic| cyclic.errors().last()) {
+            error = Some(e);
+            redraw = true;
+        }
+        if let Some(e) = sinks.errors().pop() {
+            error = Some(e);
+            redraw = true;
+        }
+        if flash_until.is_some_and(|until| Instant::now() >= until) {
+            flash_until = None;
+            redraw = true;
+        }
+        redraw |= drawn.elapsed() >= REPAINT_INTERVAL;
+
+        // Rows go stale without any frame arriving, so keep re-sorting.
+        dirty |= view.sink_stale && !filtering;
+        if dirty {
+            let selected = state.selected().map(|row| (row, order[row]));
+            order = (0..msgs.len())
+                .filter(|&i| filter.matches(&msgs[i]) && !view.hides(&msgs[i]))
+                .collect();
+            order.sort_by(|&a, &b| compare(&msgs[a], &msgs[b], &view));
+            if let Some((row, selected)) = selected {
+                // If the selected row was filtered out, e.g. un-ignored
+                // while showing only ignored IDs, stay at the same height.
+                state.select(
+                    order
+                        .iter()
+                        .position(|&i| i == selected)
+                        .or_else(|| order.len().checked_sub(1).map(|last| row.min(last))),
+                );
             }
-        });
+            dirty = false;
+        }
 
-        terminal.draw(|f| {
-            let block = if state.selected().is_none() {
-                Block::new().title("canalyzer | F)ilter; Q)uit")
-            } else {
-                Block::new().title("canalyzer | I)gnore; P)in to top; Exit F)iltering")
-            }
-            .title_position(Position::Bottom)
-            .title_style(Style::new().yellow().on_blue());
-            let cols = f.size().width as usize / 17;
-            f.render_stateful_widget(
-                Table::new(
-                    msgs.iter().map(|m| m.as_row(cols)),
-                    iter::once(Constraint::Length(6))
-                        .chain(iter::repeat(Constraint::Length(16)).take(cols)),
-                )
-                .highlight_symbol(">")
-                .block(block),
-                f.size(),
-                &mut state,
-            );
-        })?;
+        if redraw {
+            view.show_source = msgs.iter().any(|msg| msg.source != msgs[0].source);
+            terminal.draw(|f| {
+                let status = match connection {
+                    None => "",
+                    Some(canbus::Connection::Connected) => " (connected)",
+                    Some(canbus::Connection::Reconnecting) => " (reconnecting)",
+                };
+                // Ages keep ticking while paused so the colors still say how
+                // old the snapshot is.
+                let mut status = if paused {
+                    format!("{status} | PAUSED, ages still ticking")
+                } else {
+                    status.to_string()
+                };
+                status += &format!(" | load {:.1}%", bus_load(&msgs, args.bitrate));
+                let rate: f64 = msgs.iter().map(|msg| msg.rate(&view)).sum();
+                let elapsed = started.elapsed().as_secs();
+                status += &format!(
+                    " | {received} frames, {} IDs, {rate:.0}/s | {}:{:02}:{:02}",
+                    msgs.len(),
+                    elapsed / 3600,
+                    elapsed / 60 % 60,
+                    elapsed % 60
+                );
+                if bad_lines > 0 {
+                    status += &format!(" | {bad_lines} bad lines");
+                }
+                let cycling = cyclic.as_ref().map_or(0, |cyclic| {
+                    cyclic.entries().iter().filter(|entry| !entry.paused).count()
+                });
+                if cycling > 0 {
+                    status += &format!(" | {cycling} cyclic");
+                }
+                if !marked.is_empty() {
+                    status += &format!(" | {} marked", marked.len());
+                }
+                if let (Some(id), None) = (&hover, state.selected()) {
+                    status += &format!(" | cursor on {id}");
+                }
+                if view.sort != SortMode::Recency {
+                    status += &format!(" | by {}", view.sort.name());
+                }
+                let mut filters = String::new();
+                if prompt == Some(Prompt::Search) {
+                    filters += &format!(" | /{input}_");
+                } else if !filter.query.is_empty() {
+                    filters += &format!(" | /{}", filter.query);
+                }
+                if prompt == Some(Prompt::Regex) {
+                    filters += &format!(" | regex {input}_");
+                    if let Some(e) = &input_error {
+                        filters += &format!(" ({e})");
+                    }
+                } else if let Some(re) = &filter.regex {
+                    filters += &format!(" | regex {}", re.as_str());
+                }
+                if prompt == Some(Prompt::IdRange) {
+                    filters += &format!(" | ids {input}_");
+                    if let Some(e) = &input_error {
+                        filters += &format!(" ({e})");
+                    }
+                } else if let Some(range) = &filter.id_range {
+                    filters += &format!(" | ids {range}");
+                }
+                if filter.only_ignored {
+                    filters += " | ignored only";
+                }
+                if prompt == Some(Prompt::Jump) {
+                    filters += &format!(" | jump to {input}_");
+                }
+                if prompt == Some(Prompt::Transmit) {
+                    filters += &format!(" | send {input}_");
+                    if !input.is_empty() && canbus::parse_frame(&input).is_none() {
+                        filters += " (expected <id>#<data> or <id>#R)";
+                    }
+                }
+                if prompt == Some(Prompt::Note) {
+                    filters += &format!(" | note {input}_");
+                }
+                if prompt == Some(Prompt::Tags) {
+                    filters += &format!(" | tags {input}_");
+                }
+                if prompt == Some(Prompt::Collapse) {
+                    filters += &format!(" | collapse or expand {input}_");
+                }
+                if prompt == Some(Prompt::IgnorePattern) {
+                    filters += &format!(" | ignore {input}_");
+                    if !input.is_empty() && input.parse::<canbus::IdPattern>().is_err() {
+                        filters += " (expected e.g. 7xx, 18DA* or 700/F00)";
+                    }
+                }
+                if let Some(Prompt::Cyclic(_)) = prompt {
+                    filters += &format!(" | cyclic {input}_");
+                    if !input.is_empty() && cyclic::parse(&input).is_none() {
+                        filters += " (expected <id>#<data> <ms>)";
+                    }
+                }
+                let mut block = if confirm_clear {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | Clear all messages? y) yes; any other key cancels"
+                    ))
+                } else if prompt == Some(Prompt::Transmit) {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | Enter) send; Up/Down) history; Esc) cancel"
+                    ))
+                } else if prompt == Some(Prompt::Jump) {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | Enter) jump; Esc) cancel"
+                    ))
+                } else if prompt == Some(Prompt::Collapse) {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | Enter) toggle; Esc) cancel"
+                    ))
+                } else if let Some(
+                    Prompt::Cyclic(_) | Prompt::IgnorePattern | Prompt::Note | Prompt::Tags,
+                ) = prompt
+                {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | Enter) save; Esc) cancel"
+                    ))
+                } else if prompt.is_some() {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | Enter) done; Esc) clear"
+                    ))
+                } else if state.selected().is_none() {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | f) filter; /) search; e) expression; h) hex/dec/ascii; Space) pause; ?) help; q) quit"
+                    ))
+                } else {
+                    Block::new().title(format!(
+                        "canalyzer{status}{filters} | i) ignore; p) pin; r) reset; t) trigger; m) mute byte; ?) help; f) exit filtering"
+                    ))
+                }
+                .title_position(Position::Bottom)
+                .title_style(
+                    if flash_until.is_some_and(|until| Instant::now() < until) {
+                        view.theme.flash
+                    } else {
+                        view.theme.title
+                    },
+                );
+                if let Some(error) = &error {
+                    block = block.title(
+                        Title::from(Line::styled(error.as_str(), view.theme.error))
+                            .alignment(Alignment::Right),
+                    );
+                } else if let Some(notice) = &notice {
+                    block = block.title(
+                        Title::from(Line::styled(notice.as_str(), view.theme.notice))
+                            .alignment(Alignment::Right),
+                    );
+                }
+                let names = view.show_names.then_some(&names);
+                let min_id_width = if msgs.iter().any(|m| m.is_extended) {
+                    9
+                } else {
+                    6
+                };
+                // Group headers only have the ID column to fit in.
+                let header_width = match view.grouped {
+                    true => msgs
+                        .iter()
+                        .map(|msg| msg.group().chars().count() as u16 + 9)
+                        .max()
+                        .unwrap_or_default(),
+                    false => 0,
+                };
+                let id_width = order
+                    .iter()
+                    .map(|&i| msgs[i].label(names).len() as u16 + 1)
+                    .fold(min_id_width.max(header_width), u16::max);
+                let mut widths = vec![id_width];
+                if view.show_source {
+                    let source_width = msgs.iter().map(|msg| msg.source.len()).max();
+                    widths.push(source_width.unwrap_or_default() as u16 + 1);
+                }
+                widths.push(2);
+                if view.show_rate {
+                    widths.push(5);
+                }
+                if view.show_time {
+                    widths.push(12);
+                }
+                if view.show_count {
+                    widths.push(7);
+                }
+                // Value cells fit the longest payload on screen, and as many of
+                // them as fit in what the other columns, the highlight symbol
+                // and the ASCII sidebar leave.
+                let width = args
+                    .col_width
+                    .unwrap_or_else(|| value_width(&msgs, &order, &view));
+                let sidebar = if state.selected().is_some() && view.show_ascii {
+                    11
+                } else {
+                    0
+                };
+                let used = widths.iter().map(|width| width + 1).sum::<u16>() + 1 + sidebar;
+                fitting = match view.compact {
+                    true => 1,
+                    false => (f.size().width.saturating_sub(used) / (width + 1)).max(1) as usize,
+                };
+                let cols = view.max_cols.map_or(fitting, |max| fitting.min(max));
+                let widths: Vec<_> = widths
+                    .into_iter()
+                    .chain(iter::repeat_n(width, cols))
+                    .map(Constraint::Length)
+                    .collect();
+                // The raw input panel takes the bottom of the screen, below
+                // any detail panes.
+                let screen = if show_raw {
+                    let split = Layout::default()
+                        .direction(Direction::Vertical)
+                        .constraints([
+                            Constraint::Min(0),
+                            Constraint::Length(RAW_LINES as u16 + 1),
+                        ])
+                        .split(f.size());
+                    f.render_widget(raw_panel(&raw_lines), split[1]);
+                    split[0]
+                } else {
+                    f.size()
+                };
+                let area = match state.selected() {
+                    Some(row) => {
+                        let msg = &msgs[order[row]];
+                        let signals = msg.definition(dbc).map_or(0, |def| def.signals.len());
+                        let bytes = msg.values.back().unwrap().data.len().div_ceil(2);
+                        let payload_lines =
+                            ("payload ".len() as u16 + view.mode.width(bytes)).div_ceil(f.size().width.max(1));
+                        let detail_height = 6 + payload_lines + signals as u16 + msg.sequence.is_some() as u16;
+                        let stats_height = if view.show_stats {
+                            2 + bytes as u16
+                        } else {
+                            0
+                        };
+                        let bits_height = if view.show_bits {
+                            2 + bytes as u16
+                        } else {
+                            0
+                        };
+                        let graph_height = if view.show_graph { 5 } else { 0 };
+                        let split = Layout::default()
+                            .direction(Direction::Vertical)
+                            .constraints([
+                                Constraint::Min(0),
+                                Constraint::Length(detail_height),
+                                Constraint::Length(stats_height),
+                                Constraint::Length(bits_height),
+                                Constraint::Length(graph_height),
+                            ])
+                            .split(screen);
+                        f.render_widget(msgs[order[row]].detail(dbc, &view, byte, watch), split[1]);
+                        if view.show_stats {
+                            f.render_widget(msgs[order[row]].stats(view.mode, byte, &view.theme), split[2]);
+                        }
+                        if view.show_bits {
+                            f.render_widget(msgs[order[row]].bits(byte, &view.theme), split[3]);
+                        }
+                        if view.show_graph {
+                            let history = msgs[order[row]].watch_history(byte, watch);
+                            let shown = &history[history.len().saturating_sub(split[4].width as usize)..];
+                            f.render_widget(
+                                Sparkline::default()
+                                    .block(Block::new().borders(Borders::TOP).title(format!(
+                                        "{} as {watch} over the last {} frames",
+                                        bytes_label(byte, watch),
+                                        shown.len()
+                                    )))
+                                    .data(shown)
+                                    .max(watch.max())
+                                    .style(view.theme.changed),
+                                split[4],
+                            );
+                        }
+                        if view.show_ascii {
+                            let side = Layout::default()
+                                .direction(Direction::Horizontal)
+                                .constraints([Constraint::Min(0), Constraint::Length(11)])
+                                .split(split[0]);
+                            f.render_widget(msgs[order[row]].ascii(byte, &view.theme), side[1]);
+                            side[0]
+                        } else {
+                            split[0]
+                        }
+                    }
+                    None => screen,
+                };
+                // The bottom line of the area holds the title bar.
+                page = area.height.saturating_sub(1).max(1) as usize;
+                // Without a selection to keep in view, a terminal that grew
+                // would otherwise leave blank space below the last row.
+                if state.selected().is_none() {
+                    *state.offset_mut() = state.offset().min(order.len().saturating_sub(page));
+                }
+                let hovered = hover.as_deref().filter(|_| state.selected().is_none());
+                let rows: Vec<_> = order.iter().map(|&i| {
+                    let row = msgs[i].as_row(cols, width, &view, names, rules, marked.contains(&msgs[i].id));
+                    match hovered == Some(msgs[i].id.as_str()) {
+                        true => row.underlined(),
+                        false => row,
+                    }
+                }).collect();
+                let (rows, positions) = match view.grouped {
+                    true => {
+                        let groups: Vec<_> = order.iter().map(|&i| msgs[i].group()).collect();
+                        let mut collapsed = BTreeMap::<&str, usize>::new();
+                        for msg in msgs.iter().filter(|msg| filter.matches(msg) && view.hides(msg)) {
+                            *collapsed.entry(msg.group()).or_default() += 1;
+                        }
+                        with_headers(rows, &groups, &collapsed, &view.theme)
+                    }
+                    false => (rows, (0..order.len()).collect()),
+                };
+                // The table's rows may include group headers, so the
+                // selection and scroll position are translated to them and
+                // back. A group's first row scrolls in with its header.
+                let offset = positions.get(state.offset()).map_or(0, |&pos| {
+                    let header_above = pos > 0
+                        && (state.offset() == 0 || positions[state.offset() - 1] != pos - 1);
+                    pos - header_above as usize
+                });
+                // Without a selection the table would scroll back to its
+                // first row, so the scrolled-past rows are left out instead.
+                let (rows, offset) = match state.selected() {
+                    Some(_) => (rows, offset),
+                    None => (rows.into_iter().skip(offset).collect(), 0),
+                };
+                let mut shown = TableState::default()
+                    .with_selected(state.selected().map(|row| positions[row]))
+                    .with_offset(offset);
+                f.render_stateful_widget(
+                    Table::new(rows, widths)
+                        .highlight_symbol(">")
+                        .highlight_style(view.theme.selection)
+                        .block(block),
+                    area,
+                    &mut shown,
+                );
+                if state.selected().is_some() {
+                    if let Some(row) = positions.iter().position(|&pos| pos >= shown.offset()) {
+                        *state.offset_mut() = row;
+                    }
+                }
+                if show_help {
+                    let area = centered(f.size(), 50, HELP.len() as u16 + 2);
+                    f.render_widget(Clear, area);
+                    f.render_widget(help(&view.theme), area);
+                }
+                if let (true, Some(cyclic)) = (show_cyclic, &cyclic) {
+                    let entries = cyclic.entries();
+                    let area = centered(f.size(), 60, entries.len().max(1) as u16 + 2);
+                    f.render_widget(Clear, area);
+                    f.render_widget(cyclic_panel(&entries, cyclic_row, &view.theme), area);
+                }
+                if show_patterns {
+                    let patterns = &persisted.ignore_patterns;
+                    let area = centered(f.size(), 40, patterns.len().max(1) as u16 + 2);
+                    f.render_widget(Clear, area);
+                    f.render_widget(patterns_panel(patterns, pattern_row, &view.theme), area);
+                }
+                if show_bad {
+                    let area = centered(f.size(), 80, recent_bad.len().max(1) as u16 + 2);
+                    f.render_widget(Clear, area);
+                    f.render_widget(bad_lines_list(&recent_bad), area);
+                }
+                if order.len() > page {
+                    let mut scroll = ScrollbarState::new(order.len())
+                        .position(state.selected().unwrap_or(state.offset()))
+                        .viewport_content_length(page);
+                    f.render_stateful_widget(
+                        Scrollbar::new(ScrollbarOrientation::VerticalRight),
+                        Rect {
+                            height: page as u16,
+                            ..area
+                        },
+                        &mut scroll,
+                    );
+                }
+            })?;
+            drawn = Instant::now();
+            redraw = false;
+        }
 
-        if crossterm::event::poll(std::time::Duration::from_millis(250))? {
-            if let crossterm::event::Event::Key(key) = crossterm::event::read()? {
-                if key.kind == crossterm::event::KeyEventKind::Press {
+        // With events still queued, only check for keys before going on.
+        let timeout = if handled == BATCH {
+            Duration::ZERO
+        } else {
+            args.poll_interval
+                .min(REPAINT_INTERVAL.saturating_sub(drawn.elapsed()))
+        };
+        if crossterm::event::poll(timeout)? {
+            let event = crossterm::event::read()?;
+            redraw = true;
+            if let crossterm::event::Event::Resize(..) = event {
+                // Repaint everything instead of diffing against a buffer of
+                // the old size, which can leave parts of the last frame
+                // behind. The next draw lays out the table for the new size
+                // and scrolls the selection back into view.
+                terminal.clear()?;
+            }
+            if let crossterm::event::Event::Key(key) = event {
+                if let (
+                    crossterm::event::KeyEventKind::Press,
+                    Some(
+                        sending @ (Prompt::Transmit
+                        | Prompt::Cyclic(_)
+                        | Prompt::Jump
+                        | Prompt::IgnorePattern
+                        | Prompt::Note
+                        | Prompt::Tags
+                        | Prompt::Collapse),
+                    ),
+                ) = (key.kind, prompt)
+                {
+                    match key.code {
+                        crossterm::event::KeyCode::Enter => {
+                            if let (Prompt::Cyclic(slot), Some((frame, period)), Some(cyclic)) =
+                                (sending, cyclic::parse(&input), &cyclic)
+                            {
+                                match slot {
+                                    Some(i) => cyclic.edit(i, frame, period),
+                                    None => {
+                                        cyclic.add(frame, period);
+                                        cyclic_row = cyclic.entries().len() - 1;
+                                    }
+                                }
+                                prompt = None;
+                                input.clear();
+                            } else if let (Prompt::Transmit, Some(frame), Some(transmitter)) =
+                                (sending, canbus::parse_frame(&input), &transmitter)
+                            {
+                                match transmitter.lock().unwrap().transmit(&frame) {
+                                    Ok(()) => {
+                                        notice = Some(format!("sent {}", input.trim()));
+                                        error = None;
+                                    }
+                                    Err(e) => error = Some(format!("transmit failed: {e}")),
+                                }
+                                sent.retain(|frame| frame != input.trim());
+                                sent.push(input.trim().to_string());
+                                if sent.len() > SENT_HISTORY {
+                                    sent.remove(0);
+                                }
+                                prompt = None;
+                                input.clear();
+                            } else if let (Prompt::IgnorePattern, Ok(pattern)) =
+                                (sending, input.parse::<canbus::IdPattern>())
+                            {
+                                for msg in msgs.iter_mut().filter(|msg| pattern.matches(&msg.id)) {
+                                    msg.ignored = true;
+                                }
+                                persisted.ignore_patterns.push(pattern);
+                                pattern_row = persisted.ignore_patterns.len() - 1;
+                                dirty = true;
+                                prompt = None;
+                                input.clear();
+                            } else if let (Prompt::Note, Some(row)) = (sending, state.selected()) {
+                                let note = input.trim();
+                                msgs[order[row]].note =
+                                    (!note.is_empty()).then(|| note.to_string());
+                                prompt = None;
+                                input.clear();
+                            } else if let (Prompt::Tags, Some(row)) = (sending, state.selected()) {
+                                msgs[order[row]].tags = input
+                                    .split(',')
+                                    .map(str::trim)
+                                    .filter(|tag| !tag.is_empty())
+                                    .map(str::to_string)
+                                    .collect();
+                                dirty = true;
+                                prompt = None;
+                                input.clear();
+                            } else if sending == Prompt::Collapse {
+                                let group = input.trim();
+                                if !view.collapsed.remove(group) {
+                                    if msgs.iter().any(|msg| msg.group() == group) {
+                                        view.collapsed.insert(group.to_string());
+                                    } else {
+                                        error = Some(format!("no group {group}"));
+                                    }
+                                }
+                                dirty = true;
+                                prompt = None;
+                                input.clear();
+                            } else if sending == Prompt::Jump {
+                                let wanted = input.trim();
+                                let id = canbus::parse_id(wanted);
+                                match order.iter().position(|&i| {
+                                    id.is_some() && msgs[i].id_value == id
+                                        || msgs[i].id.eq_ignore_ascii_case(wanted)
+                                }) {
+                                    Some(row) => state.select(Some(row)),
+                                    None => error = Some(format!("no row for ID {wanted}")),
+                                }
+                                prompt = None;
+                                input.clear();
+                            }
+                        }
+                        crossterm::event::KeyCode::Esc => {
+                            prompt = None;
+                            input.clear();
+                        }
+                        crossterm::event::KeyCode::Up
+                            if sending == Prompt::Transmit && !sent.is_empty() =>
+                        {
+                            let i = recall.map_or(sent.len() - 1, |i: usize| i.saturating_sub(1));
+                            input = sent[i].clone();
+                            recall = Some(i);
+                        }
+                        crossterm::event::KeyCode::Down if sending == Prompt::Transmit => {
+                            recall = recall.map(|i| i + 1).filter(|&i| i < sent.len());
+                            input = recall.map(|i| sent[i].clone()).unwrap_or_default();
+                        }
+                        crossterm::event::KeyCode::Backspace => {
+                            input.pop();
+                        }
+                        crossterm::event::KeyCode::Char(c)
+                            if !key
+                                .modifiers
+                                .contains(crossterm::event::KeyModifiers::CONTROL) =>
+                        {
+                            input.push(c)
+                        }
+                        _ => {}
+                    }
+                } else if let (crossterm::event::KeyEventKind::Press, Some(editing)) =
+                    (key.kind, prompt)
+                {
+                    match key.code {
+                        crossterm::event::KeyCode::Enter if input_error.is_none() => prompt = None,
+                        crossterm::event::KeyCode::Esc => {
+                            prompt = None;
+                            input.clear();
+                        }
+                        crossterm::event::KeyCode::Backspace => {
+                            input.pop();
+                        }
+                        crossterm::event::KeyCode::Char(c)
+                            if !key
+                                .modifiers
+                                .contains(crossterm::event::KeyModifiers::CONTROL) =>
+                        {
+                            input.push(c)
+                        }
+                        _ => {}
+                    }
+                    match editing {
+                        Prompt::Search => filter.query = input.clone(),
+                        Prompt::Regex if input.is_empty() => {
+                            filter.regex = None;
+                            input_error = None;
+                        }
+                        Prompt::Regex => match Regex::new(&input) {
+                            Ok(re) => {
+                                filter.regex = Some(re);
+                                input_error = None;
+                            }
+                            Err(e) => input_error = Some(e.to_string()),
+                        },
+                        Prompt::IdRange if input.is_empty() => {
+                            filter.id_range = None;
+                            input_error = None;
+                        }
+                        Prompt::IdRange => match input.parse() {
+                            Ok(range) => {
+                                filter.id_range = Some(range);
+                                input_error = None;
+                            }
+                            Err(e) => input_error = Some(e.to_string()),
+                        },
+                        Prompt::Jump
+                        | Prompt::IgnorePattern
+                        | Prompt::Note
+                        | Prompt::Tags
+                        | Prompt::Collapse
+                        | Prompt::Transmit
+                        | Prompt::Cyclic(_) => unreachable!(),
+                    }
+                    dirty = true;
+                } else if let (crossterm::event::KeyEventKind::Press, true, Some(cyclic)) =
+                    (key.kind, show_cyclic, &cyclic)
+                {
+                    let len = cyclic.entries().len();
+                    match key.code {
+                        crossterm::event::KeyCode::Up => cyclic_row = cyclic_row.saturating_sub(1),
+                        crossterm::event::KeyCode::Down => {
+                            cyclic_row = (cyclic_row + 1).min(len.saturating_sub(1))
+                        }
+                        crossterm::event::KeyCode::Char('a') => {
+                            prompt = Some(Prompt::Cyclic(None));
+                            input.clear();
+                        }
+                        crossterm::event::KeyCode::Char('e') | crossterm::event::KeyCode::Enter
+                            if cyclic_row < len =>
+                        {
+                            let entry = &cyclic.entries()[cyclic_row];
+                            input = format!("{} {}", entry.frame, entry.period.as_millis());
+                            prompt = Some(Prompt::Cyclic(Some(cyclic_row)));
+                        }
+                        crossterm::event::KeyCode::Char(' ') => cyclic.toggle_paused(cyclic_row),
+                        crossterm::event::KeyCode::Char('x')
+                        | crossterm::event::KeyCode::Delete => {
+                            cyclic.remove(cyclic_row);
+                            cyclic_row = cyclic_row.min(len.saturating_sub(2));
+                        }
+                        crossterm::event::KeyCode::Esc | crossterm::event::KeyCode::Char('W') => {
+                            show_cyclic = false
+                        }
+                        _ => {}
+                    }
+                } else if key.kind == crossterm::event::KeyEventKind::Press && show_patterns {
+                    let len = persisted.ignore_patterns.len();
+                    match key.code {
+                        crossterm::event::KeyCode::Up => {
+                            pattern_row = pattern_row.saturating_sub(1)
+                        }
+                        crossterm::event::KeyCode::Down => {
+                            pattern_row = (pattern_row + 1).min(len.saturating_sub(1))
+                        }
+                        crossterm::event::KeyCode::Char('a') => {
+                            prompt = Some(Prompt::IgnorePattern);
+                            input.clear();
+                        }
+                        crossterm::event::KeyCode::Char('x')
+                        | crossterm::event::KeyCode::Delete
+                            if pattern_row < len =>
+                        {
+                            let pattern = persisted.ignore_patterns.remove(pattern_row);
+                            // Rows it covered go back to what the saved IDs and
+                            // the remaining patterns say.
+                            for msg in msgs.iter_mut().filter(|msg| pattern.matches(&msg.id)) {
+                                msg.ignored = persisted.ignores(&msg.id);
+                            }
+                            pattern_row = pattern_row.min(len.saturating_sub(2));
+                            dirty = true;
+                        }
+                        crossterm::event::KeyCode::Esc | crossterm::event::KeyCode::Char('I') => {
+                            show_patterns = false
+                        }
+                        _ => {}
+                    }
+                } else if key.kind == crossterm::event::KeyEventKind::Press
+                    && (show_help || show_bad)
+                {
+                    show_help = false;
+                    show_bad = false;
+                } else if key.kind == crossterm::event::KeyEventKind::Press && confirm_clear {
+                    confirm_clear = false;
+                    if key.code == crossterm::event::KeyCode::Char('y') {
+                        // Remember this session's choices so IDs that come
+                        // back are ignored or pinned as before.
+                        for msg in &msgs {
+                            persisted.update(
+                                &msg.id,
+                                msg.ignored,
+                                msg.pinned,
+                                msg.note.as_deref(),
+                                &msg.tags,
+                            );
+                        }
+                        msgs.clear();
+                        index.clear();
+                        order.clear();
+                        state = TableState::default();
+                    }
+                } else if key.kind == crossterm::event::KeyEventKind::Press {
                     if key.code == crossterm::event::KeyCode::Char('q') {
                         break;
                     }
+                    if key.code == crossterm::event::KeyCode::Char('/') {
+                        prompt = Some(Prompt::Search);
+                        input = filter.query.clone();
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('e') {
+                        prompt = Some(Prompt::Regex);
+                        input = filter
+                            .regex
+                            .as_ref()
+                            .map(|re| re.as_str().to_string())
+                            .unwrap_or_default();
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('j') {
+                        prompt = Some(Prompt::Jump);
+                        input.clear();
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('R') {
+                        prompt = Some(Prompt::IdRange);
+                        input = filter
+                            .id_range
+                            .map(|range| range.to_string())
+                            .unwrap_or_default();
+                    }
+                    if key.code == crossterm::event::KeyCode::Esc {
+                        filter = Filter::default();
+                        hover = None;
+                        dirty = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('?') {
+                        show_help = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('w') {
+                        if transmitter.is_some() {
+                            prompt = Some(Prompt::Transmit);
+                            input.clear();
+                            recall = None;
+                        } else {
+                            error = Some("this source can't transmit".to_string());
+                        }
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('W') {
+                        if cyclic.is_some() {
+                            show_cyclic = true;
+                        } else {
+                            error = Some("this source can't transmit".to_string());
+                        }
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('u') {
+                        show_bad = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('I') {
+                        show_patterns = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('U') {
+                        show_raw = !show_raw;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('c') {
+                        confirm_clear = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char(' ') {
+                        paused = !paused;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('o') {
+                        filter.only_ignored = !filter.only_ignored;
+                        dirty = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('h') {
+                        view.mode = view.mode.next();
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('n') {
+                        view.show_names = !view.show_names;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('z') {
+                        view.show_rate = !view.show_rate;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('g') {
+                        view.show_graph = !view.show_graph;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('a') {
+                        view.show_ascii = !view.show_ascii;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('S') {
+                        view.show_stats = !view.show_stats;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('B') {
+                        view.show_bits = !view.show_bits;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('s') {
+                        view.sort = view.sort.next();
+                        dirty = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('G') {
+                        view.grouped = !view.grouped;
+                        dirty = true;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('C') && view.grouped {
+                        // Start from the group of the row in question.
+                        let row = state.selected().or_else(|| {
+                            let id = hover.as_ref()?;
+                            order.iter().position(|&i| msgs[i].id == *id)
+                        });
+                        prompt = Some(Prompt::Collapse);
+                        input =
+                            row.map_or(String::new(), |row| msgs[order[row]].group().to_string());
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('l') {
+                        view.show_time = !view.show_time;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('L') {
+                        view.compact = !view.compact;
+                    }
+                    if let crossterm::event::KeyCode::Char(c @ ('<' | '>')) = key.code {
+                        let shown = view.max_cols.unwrap_or(fitting).min(fitting);
+                        view.max_cols = match c {
+                            '<' => Some(shown.saturating_sub(1).max(1)),
+                            _ => Some(shown + 1).filter(|&max| max < fitting),
+                        };
+                        notice = Some(match view.max_cols {
+                            Some(max) => format!("showing at most {max} values per ID"),
+                            None => "showing as many values as fit".to_string(),
+                        });
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('#') {
+                        view.show_count = !view.show_count;
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('x') {
+                        let path = args
+                            .export_csv
+                            .as_deref()
+                            .unwrap_or(Path::new("canalyzer.csv"));
+                        let shown = order.iter().map(|&i| &msgs[i]);
+                        let written = match view.relative_to {
+                            Some(start) => export::csv(path, &export::rebased(shown, start)),
+                            None => export::csv(path, shown),
+                        };
+                        match written {
+                            Ok(()) => notice = Some(format!("exported {}", path.display())),
+                            Err(e) => error = Some(format!("{e:#}")),
+                        }
+                    }
+                    if key.code == crossterm::event::KeyCode::Char('d') {
+                        let path = args
+                            .export_candump
+                            .as_deref()
+                            .unwrap_or(Path::new("canalyzer.log"));
+                        let shown = order.iter().map(|&i| &msgs[i]);
+                        let interface = args.candump_interface();
+                        let written = match view.relative_to {
+                            Some(start) => {
+                                export::candump(path, interface, &export::rebased(shown, start))
+                            }
+                            None => export::candump(path, interface, shown),
+                        };
+                        match written {
+                            Ok(()) => notice = Some(format!("exported {}", path.display())),
+                            Err(e) => error = Some(format!("{e:#}")),
+                        }
+                    }
+                    // Rows are looked up by ID, as the cursor's may have moved
+                    // or been filtered out since the last key.
+                    let hovered = hover
+                        .as_ref()
+                        .and_then(|id| order.iter().position(|&i| msgs[i].id == *id));
                     if key.code == crossterm::event::KeyCode::Char('f') {
                         state.select(match state.selected() {
-                            None => Some(0),
-                            Some(_) => None,
-                        })
+                            None if !order.is_empty() => Some(hovered.unwrap_or(0)),
+                            _ => None,
+                        });
+                        hover = None;
+                        dirty = true;
+                    }
+                    if let (None, Some(last)) = (state.selected(), order.len().checked_sub(1)) {
+                        let from = hovered.unwrap_or(state.offset());
+                        let row = match key.code {
+                            crossterm::event::KeyCode::Down if hovered.is_some() => {
+                                Some((from + 1).min(last))
+                            }
+                            crossterm::event::KeyCode::Up if hovered.is_some() => {
+                                Some(from.saturating_sub(1))
+                            }
+                            crossterm::event::KeyCode::Down | crossterm::event::KeyCode::Up => {
+                                Some(from.min(last))
+                            }
+                            _ => None,
+                        };
+                        if let Some(row) = row {
+                            hover = Some(msgs[order[row]].id.clone());
+                            // Scroll just enough to keep the cursor on screen.
+                            *state.offset_mut() =
+                                state.offset().min(row).max((row + 1).saturating_sub(page));
+                        }
+                        if let crossterm::event::KeyCode::Char(c @ ('i' | 'p')) = key.code {
+                            let msg = &mut msgs[order[from.min(last)]];
+                            let flag = match c {
+                                'i' => &mut msg.ignored,
+                                _ => &mut msg.pinned,
+                            };
+                            *flag = !*flag;
+                            dirty = true;
+                        }
+                    }
+                    match state.selected() {
+                        Some(row) => {
+                            let last = order.len().saturating_sub(1);
+                            if let Some(row) = paged(key.code, row, page, last) {
+                                state.select(Some(row));
+                            }
+                        }
+                        None => {
+                            let last = order.len().saturating_sub(page);
+                            if let Some(offset) = paged(key.code, state.offset(), page, last) {
+                                *state.offset_mut() = offset;
+                            }
+                        }
                     }
                     if let Some(row) = state.selected() {
-                        if key.code == crossterm::event::KeyCode::Char('i') {
-                            let msg = msgs.get_mut(row).unwrap();
-                            msg.ignored = !msg.ignored;
+                        if key.code == crossterm::event::KeyCode::Left {
+                            byte = byte.saturating_sub(1);
+                        }
+                        if key.code == crossterm::event::KeyCode::Right {
+                            byte = (byte + 1).min(8 - watch.len);
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('[') {
+                            watch.len = (watch.len - 1).max(1);
                         }
-                        if key.code == crossterm::event::KeyCode::Char('p') {
-                            let msg = msgs.get_mut(row).unwrap();
-                            msg.pinned = !msg.pinned;
+                        if key.code == crossterm::event::KeyCode::Char(']') {
+                            watch.len = (watch.len + 1).min(8 - byte);
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('E') {
+                            watch.big_endian = !watch.big_endian;
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('-') {
+                            watch.signed = !watch.signed;
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('m') {
+                            let ignored = &mut msgs[order[row]].ignored_bytes;
+                            if !ignored.remove(&byte) {
+                                ignored.insert(byte);
+                            }
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('K') {
+                            let msg = &mut msgs[order[row]];
+                            msg.sequence = match &msg.sequence {
+                                Some(sequence) if sequence.byte == byte => None,
+                                _ => {
+                                    let history: Vec<_> =
+                                        msg.values.iter().map(Value::bytes).collect();
+                                    Some(Sequence::new(byte, history.iter().map(Vec::as_slice)))
+                                }
+                            };
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('t') {
+                            let msg = &msgs[order[row]];
+                            let trigger = Trigger::new(&msg.id, &msg.values.back().unwrap().data);
+                            notice = Some(match triggers.iter().position(|t| *t == trigger) {
+                                Some(i) => {
+                                    triggers.remove(i);
+                                    format!("disarmed {trigger}")
+                                }
+                                None => {
+                                    let armed = format!("armed {trigger}");
+                                    triggers.push(trigger);
+                                    armed
+                                }
+                            });
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('y') {
+                            let msg = &msgs[order[row]];
+                            let latest = msg.values.back().unwrap();
+                            let at = chrono::DateTime::<chrono::Local>::from(latest.ts);
+                            let text = format!(
+                                "{}#{} {}",
+                                msg.id,
+                                latest.data,
+                                at.format("%Y-%m-%d %H:%M:%S%.3f")
+                            );
+                            match copy_to_clipboard(&text) {
+                                Ok(()) => notice = Some(format!("copied {text}")),
+                                Err(e) => error = Some(format!("cannot copy: {e}")),
+                            }
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('b') {
+                            let msg = &mut msgs[order[row]];
+                            msg.baseline = match msg.baseline {
+                                Some(_) => None,
+                                None => msg.values.back().cloned(),
+                            };
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('r') {
+                            let values = &mut msgs[order[row]].values;
+                            values.drain(..values.len() - 1);
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('T') {
+                            prompt = Some(Prompt::Tags);
+                            input = msgs[order[row]].tags.join(", ");
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('N') {
+                            prompt = Some(Prompt::Note);
+                            input = msgs[order[row]].note.clone().unwrap_or_default();
+                        }
+                        if key.code == crossterm::event::KeyCode::Char('v') {
+                            let id = &msgs[order[row]].id;
+                            if !marked.remove(id) {
+                                marked.insert(id.clone());
+                            }
+                        }
+                        if let crossterm::event::KeyCode::Char(c @ ('i' | 'p')) = key.code {
+                            fn flag(msg: &mut Message, c: char) -> &mut bool {
+                                match c {
+                                    'i' => &mut msg.ignored,
+                                    _ => &mut msg.pinned,
+                                }
+                            }
+                            // With rows marked, the key applies to all of
+                            // them: set unless every one is already set.
+                            let targets: Vec<usize> = if marked.is_empty() {
+                                vec![order[row]]
+                            } else {
+                                (0..msgs.len())
+                                    .filter(|&i| marked.contains(&msgs[i].id))
+                                    .collect()
+                            };
+                            let on = !targets.iter().all(|&i| *flag(&mut msgs[i], c));
+                            for i in targets {
+                                *flag(&mut msgs[i], c) = on;
+                            }
+                            marked.clear();
+                            dirty = true;
                         }
                         if key.code == crossterm::event::KeyCode::Down {
-                            state.select(Some((row + 1) % msgs.len()));
+                            state.select(next_row(row, order.len()));
                         }
                         if key.code == crossterm::event::KeyCode::Up {
-                            state.select(Some((row + msgs.len() - 1) % msgs.len()));
+                            state.select(prev_row(row, order.len()));
                         }
                     }
                 }
@@ -201,9 +2783,410 @@ fn main() -> Result<()> {
         }
     }
 
-    std::io::stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
-    crossterm::terminal::disable_raw_mode()?;
-    let _ = serde_json::to_writer(std::io::stdout(), &msgs);
-    println!();
-    Ok(())
+    // Filters and collapsed groups only decide what the table shows; every
+    // row is returned for the exports and the state file, in table order.
+    msgs.sort_by(|a, b| compare(a, b, &view));
+    if !no_color {
+        persisted.theme = Some(view.theme.name.to_string());
+    }
+    persisted.view = Some(view);
+    Ok(msgs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn navigation_without_rows_selects_nothing() {
+        assert_eq!(next_row(0, 0), None);
+        assert_eq!(prev_row(0, 0), None);
+    }
+
+    #[test]
+    fn navigation_with_one_row_stays_on_it() {
+        assert_eq!(next_row(0, 1), Some(0));
+        assert_eq!(prev_row(0, 1), Some(0));
+    }
+
+    #[test]
+    fn navigation_wraps_at_both_ends() {
+        assert_eq!(next_row(0, 3), Some(1));
+        assert_eq!(next_row(2, 3), Some(0));
+        assert_eq!(prev_row(1, 3), Some(0));
+        assert_eq!(prev_row(0, 3), Some(2));
+    }
+
+    #[test]
+    fn hex_pairs_split_every_payload() {
+        let pairs = |data| hex_pairs(data).collect::<Vec<_>>();
+        assert_eq!(pairs(""), Vec::<&str>::new());
+        assert_eq!(pairs("0102"), ["01", "02"]);
+        assert_eq!(pairs("012"), ["01", "2"]);
+        assert_eq!(pairs("0é1"), ["0é", "1"]);
+    }
+
+    fn value(data: &str) -> Value {
+        Value {
+            kind: FrameKind::Data,
+            dlc: data.len() / 2,
+            data: data.to_string(),
+            ts: SystemTime::now(),
+        }
+    }
+
+    /// The cell `diff` should draw for the hex bytes in `spans` on the
+    /// monochrome theme, with hex mode's empty separators between them.
+    fn cell(spans: Vec<Span<'static>>) -> Cell<'static> {
+        let mut line = Line::default();
+        for (i, span) in spans.into_iter().enumerate() {
+            if i > 0 {
+                line.spans.push(DisplayMode::Hex.separator().into());
+            }
+            line.spans.push(span);
+        }
+        line.patch_style(Style::new().bg(Color::Reset));
+        line.into()
+    }
+
+    #[test]
+    fn diff_styles_changed_and_unchanged_bytes() {
+        let view = View {
+            theme: theme::Theme::monochrome(),
+            ..View::default()
+        };
+        let (text, changed) = (view.theme.text, view.theme.changed);
+        let check = |new: &str, old: Option<&str>, spans| {
+            let prev = old.map(value);
+            let new = value(new);
+            let diff = new.diff(prev.as_ref(), &view, &BTreeSet::new(), &[], false, 100);
+            assert_eq!(diff, cell(spans), "{} after {old:?}", new.data);
+        };
+        check(
+            "0102",
+            None,
+            vec![Span::styled("01", text), Span::styled("02", text)],
+        );
+        check(
+            "0102",
+            Some("0102"),
+            vec![Span::styled("01", text), Span::styled("02", text)],
+        );
+        check(
+            "0103",
+            Some("0102"),
+            vec![Span::styled("01", text), Span::styled("03", changed)],
+        );
+        // A byte the previous frame didn't have counts as changed.
+        check(
+            "012",
+            Some("01"),
+            vec![Span::styled("01", text), Span::styled("2", changed)],
+        );
+        check(
+            "0102",
+            Some("01"),
+            vec![Span::styled("01", text), Span::styled("02", changed)],
+        );
+        check("01", Some("0102"), vec![Span::styled("01", text)]);
+        check(
+            "013",
+            Some("012"),
+            vec![Span::styled("01", text), Span::styled("3", changed)],
+        );
+        check("", Some("01"), Vec::new());
+    }
+
+    #[test]
+    fn diff_skips_ignored_bytes_and_dims_constant_ones() {
+        let view = View {
+            theme: theme::Theme::monochrome(),
+            ..View::default()
+        };
+        let theme = &view.theme;
+        let old = value("0102");
+        let check = |ignored: &[usize], constant: &[bool], spans| {
+            let ignored = ignored.iter().copied().collect();
+            let new = value("1112");
+            let diff = new.diff(Some(&old), &view, &ignored, constant, false, 100);
+            assert_eq!(diff, cell(spans));
+        };
+        check(
+            &[1],
+            &[],
+            vec![
+                Span::styled("11", theme.changed),
+                Span::styled("12", theme.text),
+            ],
+        );
+        check(
+            &[0],
+            &[true, true],
+            vec![
+                Span::styled("11", theme.constant),
+                Span::styled("12", theme.changed),
+            ],
+        );
+    }
+
+    /// A row for `id` whose only frame arrived `age` milliseconds ago.
+    fn row(id: &str, age: u64) -> Message {
+        let mut frame = canbus::parse_frame(&format!("{id}#00")).unwrap();
+        frame.ts = SystemTime::now() - Duration::from_millis(age);
+        Message::from(frame)
+    }
+
+    fn sorted(mut msgs: Vec<Message>, view: &View) -> Vec<String> {
+        msgs.sort_by(|a, b| compare(a, b, view));
+        msgs.into_iter().map(|msg| msg.id).collect()
+    }
+
+    fn sorted_by(sort: SortMode) -> View {
+        View {
+            sort,
+            ..View::default()
+        }
+    }
+
+    #[test]
+    fn recency_sort_puts_the_latest_first() {
+        let msgs = vec![row("100", 30), row("200", 10), row("300", 20)];
+        assert_eq!(
+            sorted(msgs, &sorted_by(SortMode::Recency)),
+            ["200", "300", "100"]
+        );
+    }
+
+    #[test]
+    fn id_sort_compares_values_not_widths() {
+        let msgs = vec![
+            row("18DAF110", 0),
+            row("100", 0),
+            row("0A1", 0),
+            row("7FF", 0),
+            row("A0", 0),
+        ];
+        assert_eq!(
+            sorted(msgs, &sorted_by(SortMode::Id)),
+            ["A0", "0A1", "100", "7FF", "18DAF110"]
+        );
+        // The same value written two ways falls back to the text, then to
+        // recency.
+        let mut rows = [row("A1", 0), row("0A1", 0), row("00A1", 10), row("00A1", 0)];
+        let view = sorted_by(SortMode::Id);
+        rows.sort_by(|a, b| compare(a, b, &view));
+        let order: Vec<_> = rows.iter().map(|msg| msg.id.as_str()).collect();
+        assert_eq!(order, ["00A1", "00A1", "0A1", "A1"]);
+        assert!(rows[0].values[0].ts > rows[1].values[0].ts);
+    }
+
+    #[test]
+    fn rate_sort_puts_the_busiest_first() {
+        let mut busy = row("100", 0);
+        busy.values.extend(busy.values.clone());
+        busy.values.extend(busy.values.clone());
+        let mut idle = row("200", 0);
+        idle.values[0].ts -= Duration::from_secs(5);
+        // Ties, like two rows with one frame each, fall back to recency.
+        let msgs = vec![idle, row("300", 20), busy, row("400", 10)];
+        assert_eq!(
+            sorted(msgs, &sorted_by(SortMode::Rate)),
+            ["100", "400", "300", "200"]
+        );
+    }
+
+    #[test]
+    fn count_sort_puts_the_most_seen_first() {
+        let mut msgs = vec![row("100", 0), row("200", 20), row("300", 10), row("400", 0)];
+        msgs[0].count = 5;
+        msgs[3].count = 9;
+        assert_eq!(
+            sorted(msgs, &sorted_by(SortMode::Count)),
+            ["400", "100", "300", "200"]
+        );
+    }
+
+    #[test]
+    fn pinned_rows_lead_and_ignored_rows_trail_in_every_sort() {
+        for sort in [
+            SortMode::Recency,
+            SortMode::Id,
+            SortMode::Rate,
+            SortMode::Count,
+        ] {
+            let mut msgs = vec![
+                row("100", 10),
+                row("200", 20),
+                row("300", 30),
+                row("400", 40),
+                row("500", 50),
+                row("600", 60),
+            ];
+            msgs[1].ignored = true;
+            msgs[2].pinned = true;
+            msgs[3].ignored = true;
+            msgs[3].pinned = true;
+            msgs[4].pinned = true;
+            // Two pinned or two ignored rows are ordered by the sort, and a
+            // row that is both counts as ignored.
+            assert_eq!(
+                sorted(msgs, &sorted_by(sort)),
+                ["300", "500", "100", "600", "400", "200"],
+                "sorted by {}",
+                sort.name()
+            );
+        }
+    }
+
+    #[test]
+    fn value_cells_fit_the_longest_payload_shown() {
+        let mut long = row("100", 0);
+        long.values[0].data = "0102030405060708".to_string();
+        long.values.push_back(value("01"));
+        let mut short = row("200", 0);
+        short.values[0].data = "0102".to_string();
+        let msgs = [short, long];
+        let mut view = View::default();
+        assert_eq!(value_width(&msgs, &[0], &view), 4);
+        assert_eq!(value_width(&msgs, &[0, 1], &view), 16);
+        // Compact view only shows the latest payloads, here both short.
+        view.compact = true;
+        assert_eq!(value_width(&msgs, &[0, 1], &view), 4);
+        // With no rows, room is left for eight bytes.
+        assert_eq!(value_width(&msgs, &[], &view), 16);
+    }
+
+    /// Runs `frames` through a headless capture from a mock source, the
+    /// `n`th stamped `n` milliseconds after the first, with a line of
+    /// garbage after the first frame.
+    fn capture(frames: &[&str], persisted: &State) -> Vec<Message> {
+        let started = SystemTime::now() - Duration::from_secs(1);
+        let mut events: Vec<_> = frames
+            .iter()
+            .zip(0..)
+            .map(|(frame, i)| {
+                let mut frame = canbus::parse_frame(frame).unwrap();
+                frame.ts = started + Duration::from_millis(i);
+                canbus::Event::Frame(frame)
+            })
+            .collect();
+        events.insert(1, canbus::Event::Malformed("garbage".to_string()));
+        let source: Box<dyn Source> = Box::new(canbus::MockSource(events));
+        let bus = Bus {
+            rx: source.messages(),
+            transmitter: None,
+            started,
+        };
+        let sinks = Sinks {
+            logger: None,
+            mqtt: None,
+            metrics: None,
+            websocket: None,
+        };
+        collect(&bus, &cli::Args::default(), persisted, Vec::new(), &sinks)
+    }
+
+    #[test]
+    fn captured_frames_merge_into_sorted_diffed_rows() {
+        let frames = ["100#0102", "200#AA", "100#0103", "300#00", "0200#AB"];
+        let mut msgs = capture(&frames, &State::default());
+        let view = View {
+            theme: theme::Theme::monochrome(),
+            ..View::default()
+        };
+        msgs.sort_by(|a, b| compare(a, b, &view));
+        let rows: Vec<_> = msgs
+            .iter()
+            .map(|msg| (msg.id.as_str(), msg.count, msg.values.len()))
+            .collect();
+        assert_eq!(rows, [("200", 2, 2), ("300", 1, 1), ("100", 2, 2)]);
+
+        let theme = &view.theme;
+        let row = &msgs[2];
+        let diff = row.values[1].diff(
+            Some(&row.values[0]),
+            &view,
+            &BTreeSet::new(),
+            &[],
+            false,
+            100,
+        );
+        assert_eq!(
+            diff,
+            cell(vec![
+                Span::styled("01", theme.text),
+                Span::styled("03", theme.changed)
+            ])
+        );
+        let row = &msgs[0];
+        let diff = row.values[1].diff(
+            Some(&row.values[0]),
+            &view,
+            &BTreeSet::new(),
+            &[],
+            false,
+            100,
+        );
+        assert_eq!(diff, cell(vec![Span::styled("AB", theme.changed)]));
+    }
+
+    #[test]
+    fn captured_rows_start_ignored_and_pinned_as_saved() {
+        let persisted = State {
+            ignored: BTreeSet::from(["100".to_string()]),
+            pinned: BTreeSet::from(["300".to_string()]),
+            ..State::default()
+        };
+        let mut msgs = capture(&["100#01", "200#02", "300#03", "100#04"], &persisted);
+        msgs.sort_by(|a, b| compare(a, b, &View::default()));
+        let rows: Vec<_> = msgs
+            .iter()
+            .map(|msg| (msg.id.as_str(), msg.ignored, msg.pinned))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                ("300", false, true),
+                ("200", false, false),
+                ("100", true, false)
+            ]
+        );
+    }
+
+    /// Times `ingest` against the linear scan it replaced, on 500 IDs with
+    /// 100 frames each. Run it with
+    /// `cargo test --release ingest_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn ingest_benchmark() {
+        let frames: Vec<_> = (0..100)
+            .flat_map(|_| (0..500).map(|id| canbus::parse_frame(&format!("{id:03X}#00")).unwrap()))
+            .collect();
+        let (args, persisted) = (cli::Args::default(), State::default());
+
+        let start = Instant::now();
+        let mut msgs = Vec::new();
+        let mut idx = index(&msgs);
+        for frame in frames.iter().cloned() {
+            ingest(&mut msgs, &mut idx, frame, &args, &persisted);
+        }
+        let indexed = start.elapsed();
+        assert_eq!(msgs.len(), 500);
+
+        let start = Instant::now();
+        let mut msgs: Vec<Message> = Vec::new();
+        for frame in frames.iter().cloned() {
+            match msgs.iter_mut().find(|msg| msg.id == frame.id) {
+                Some(msg) => msg.merge(frame, args.history),
+                None => msgs.push(Message::from(frame)),
+            }
+        }
+        let scanned = start.elapsed();
+        assert_eq!(msgs.len(), 500);
+
+        println!(
+            "{} frames: indexed {indexed:?}, linear scan {scanned:?}",
+            frames.len()
+        );
+    }
 }
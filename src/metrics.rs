@@ -0,0 +1,135 @@
+use crate::Message;
+use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+#[derive(Default)]
+struct Counter {
+    total: u64,
+    /// Receive times within the last second, for the rate.
+    recent: VecDeque<SystemTime>,
+}
+
+/// Counters by source and ID, like rows.
+type Counters = BTreeMap<(String, String), Counter>;
+
+/// Per-ID frame counters and rates served to Prometheus at `/metrics` by a
+/// thread of its own. They are updated from rows as frames are added to
+/// them, so they agree with the count and rate columns.
+pub struct Metrics {
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl Metrics {
+    pub fn serve(addr: SocketAddr) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("cannot listen on {addr}"))?;
+        let counters = Arc::new(Mutex::new(BTreeMap::new()));
+        let shared = Arc::clone(&counters);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                // A client that goes away mid-request only loses its scrape.
+                let _ = respond(stream, &shared);
+            }
+        });
+        Ok(Self { counters })
+    }
+
+    /// Records the latest frame of `msg`.
+    pub fn record(&self, msg: &Message) {
+        let mut counters = self.counters.lock().unwrap();
+        let counter = counters
+            .entry((msg.source.clone(), msg.id.clone()))
+            .or_default();
+        counter.total = msg.count;
+        counter.recent.push_back(msg.values.back().unwrap().ts);
+        expire(&mut counter.recent);
+    }
+}
+
+/// Drops receive times older than a second.
+fn expire(recent: &mut VecDeque<SystemTime>) {
+    while recent
+        .front()
+        .is_some_and(|ts| ts.elapsed().is_ok_and(|age| age >= Duration::from_secs(1)))
+    {
+        recent.pop_front();
+    }
+}
+
+fn respond(stream: TcpStream, counters: &Mutex<Counters>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Skip the headers; nothing in them matters here.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut out = &stream;
+    let path = request.split(' ').nth(1).unwrap_or_default();
+    if !request.starts_with("GET ") || !(path == "/metrics" || path == "/") {
+        return write!(
+            out,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    }
+    let body = render(&mut counters.lock().unwrap());
+    write!(
+        out,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+fn render(counters: &mut Counters) -> String {
+    let mut body = String::new();
+    body += "# HELP canalyzer_frames_total Frames received per CAN ID and interface.\n";
+    body += "# TYPE canalyzer_frames_total counter\n";
+    for ((source, id), counter) in counters.iter() {
+        let _ = writeln!(
+            body,
+            "canalyzer_frames_total{{{}}} {}",
+            labels(source, id),
+            counter.total
+        );
+    }
+    body += "# HELP canalyzer_frame_rate Frames per second per CAN ID and interface over the last second.\n";
+    body += "# TYPE canalyzer_frame_rate gauge\n";
+    for ((source, id), counter) in counters.iter_mut() {
+        expire(&mut counter.recent);
+        let _ = writeln!(
+            body,
+            "canalyzer_frame_rate{{{}}} {}",
+            labels(source, id),
+            counter.recent.len()
+        );
+    }
+    body
+}
+
+/// The labels of a row's series. The source is left out when the input has
+/// none, as with the serial protocol.
+fn labels(source: &str, id: &str) -> String {
+    match source {
+        "" => format!("id=\"{}\"", escape(id)),
+        source => format!("id=\"{}\",source=\"{}\"", escape(id), escape(source)),
+    }
+}
+
+/// Escapes a label value as the exposition format requires.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
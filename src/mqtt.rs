@@ -0,0 +1,148 @@
+//! Just enough MQTT 3.1.1 to publish frames: a clean-session CONNECT,
+//! QoS 0 PUBLISH and keep-alive PINGREQ. Nothing is ever subscribed to, so
+//! the only packet read back is the CONNACK.
+
+use crate::canbus;
+use anyhow::{bail, Error};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    str::FromStr,
+    sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender, TryIter},
+    time::{Duration, UNIX_EPOCH},
+};
+
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// Frames queued for the broker; more are dropped rather than stalling the
+/// UI.
+const QUEUE: usize = 4096;
+
+/// A broker written as `mqtt://<host>[:<port>]` or just `<host>[:<port>]`.
+#[derive(Clone)]
+pub struct Broker {
+    addr: String,
+}
+
+impl FromStr for Broker {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let host = s.strip_prefix("mqtt://").unwrap_or(s).trim_end_matches('/');
+        if host.is_empty() || host.contains('/') {
+            bail!("expected mqtt://<host>[:<port>], got `{s}`");
+        }
+        let addr = match host.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => host.to_string(),
+            Some(_) => bail!("invalid port in `{s}`"),
+            None => format!("{host}:1883"),
+        };
+        Ok(Self { addr })
+    }
+}
+
+/// Publishes every frame from a thread of its own to `canalyzer/<id>`, or to
+/// `canalyzer/<source>/<id>` when the input names its interface, with a JSON
+/// payload holding the data and the timestamp in milliseconds.
+/// A lost broker is reconnected to every second; frames that arrive in the
+/// meantime are dropped, and the failure is reported through
+/// [`Publisher::errors`].
+pub struct Publisher {
+    tx: SyncSender<canbus::Message>,
+    errors: Receiver<String>,
+}
+
+impl Publisher {
+    pub fn spawn(broker: Broker) -> Self {
+        let (tx, rx) = sync_channel(QUEUE);
+        let (errors_tx, errors) = channel();
+        std::thread::spawn(move || publish(&broker, &rx, &errors_tx));
+        Self { tx, errors }
+    }
+
+    pub fn publish(&self, frame: &canbus::Message) {
+        let _ = self.tx.try_send(frame.clone());
+    }
+
+    pub fn errors(&self) -> TryIter<'_, String> {
+        self.errors.try_iter()
+    }
+}
+
+fn publish(broker: &Broker, rx: &Receiver<canbus::Message>, errors: &Sender<String>) {
+    loop {
+        let result = connect(&broker.addr).and_then(|mut stream| loop {
+            match rx.recv_timeout(KEEP_ALIVE / 2) {
+                Ok(frame) => {
+                    let topic = match frame.source.as_str() {
+                        "" => format!("canalyzer/{}", frame.id),
+                        source => format!("canalyzer/{source}/{}", frame.id),
+                    };
+                    let ts = frame.ts.duration_since(UNIX_EPOCH).unwrap_or_default();
+                    let payload = serde_json::json!({
+                        "data": frame.data,
+                        "ts": ts.as_millis() as u64,
+                    });
+                    let mut body = string(&topic);
+                    body.extend(payload.to_string().into_bytes());
+                    stream.write_all(&packet(0x30, &body))?;
+                }
+                Err(RecvTimeoutError::Timeout) => stream.write_all(&packet(0xC0, &[]))?,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        });
+        match result {
+            Ok(()) => return,
+            Err(e) => {
+                let _ = errors.send(format!("MQTT broker {}: {e}", broker.addr));
+            }
+        }
+        std::thread::sleep(RECONNECT_INTERVAL);
+        // Frames that piled up while the broker was gone are stale by now.
+        while rx.try_recv().is_ok() {}
+    }
+}
+
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(addr)?;
+    let mut body = string("MQTT");
+    body.push(4); // protocol level 3.1.1
+    body.push(0x02); // clean session
+    body.extend((KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    body.extend(string(&format!("canalyzer-{}", std::process::id())));
+    stream.write_all(&packet(0x10, &body))?;
+
+    let mut connack = [0; 4];
+    stream.read_exact(&mut connack)?;
+    match connack {
+        [0x20, 2, _, 0] => Ok(stream),
+        [0x20, 2, _, code] => Err(io::Error::other(format!(
+            "connection refused (return code {code})"
+        ))),
+        _ => Err(io::Error::other("unexpected reply to CONNECT")),
+    }
+}
+
+/// A packet with its fixed header; the remaining length is encoded seven
+/// bits at a time.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        packet.push(if len > 0 { byte | 0x80 } else { byte });
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// A length-prefixed UTF-8 string.
+fn string(s: &str) -> Vec<u8> {
+    let mut bytes = (s.len() as u16).to_be_bytes().to_vec();
+    bytes.extend_from_slice(s.as_bytes());
+    bytes
+}
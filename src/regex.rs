@@ -0,0 +1,415 @@
+//! A small backtracking regular expression matcher covering the syntax
+//! that is useful for filtering IDs and payloads: literals, `.`, `^`, `$`,
+//! character classes (`[0-9A-F]`, `[^0]`, `\d`, `\w`, `\s`), groups,
+//! alternation and the `*`, `+`, `?` and `{n,m}` quantifiers.
+
+use std::{fmt, str::FromStr};
+
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+
+#[derive(Clone)]
+struct Class {
+    negated: bool,
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+#[derive(Clone)]
+pub struct Regex {
+    source: String,
+    alternatives: Vec<Vec<Node>>,
+}
+
+impl Regex {
+    pub fn new(source: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            pos: 0,
+        };
+        let alternatives = parser.alternation()?;
+        if parser.pos < parser.chars.len() {
+            return Err(Error("unmatched `)`".into()));
+        }
+        Ok(Self {
+            source: source.to_string(),
+            alternatives,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns whether the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        (0..=text.len()).any(|start| {
+            self.alternatives
+                .iter()
+                .any(|alt| sequence(alt, &text, start, &mut |_| true))
+        })
+    }
+}
+
+impl FromStr for Regex {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+
+fn sequence(nodes: &[Node], text: &[char], i: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    let Some((first, rest)) = nodes.split_first() else {
+        return k(i);
+    };
+    match first {
+        Node::Repeat { node, min, max } => repeat(node, *min, *max, 0, rest, text, i, k),
+        _ => single(first, text, i, &mut |j| sequence(rest, text, j, k)),
+    }
+}
+
+fn single(node: &Node, text: &[char], i: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+    match node {
+        Node::Char(c) => text.get(i) == Some(c) && k(i + 1),
+        Node::Any => i < text.len() && k(i + 1),
+        Node::Class(class) => text.get(i).is_some_and(|&c| class.matches(c)) && k(i + 1),
+        Node::Start => i == 0 && k(i),
+        Node::End => i == text.len() && k(i),
+        Node::Group(alternatives) => alternatives.iter().any(|alt| sequence(alt, text, i, k)),
+        Node::Repeat { .. } => sequence(std::slice::from_ref(node), text, i, k),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn repeat(
+    node: &Node,
+    min: usize,
+    max: Option<usize>,
+    count: usize,
+    rest: &[Node],
+    text: &[char],
+    i: usize,
+    k: &mut dyn FnMut(usize) -> bool,
+) -> bool {
+    // Greedy: take one more repetition if allowed, then fall back to the
+    // rest of the sequence. An empty repetition could be repeated up to
+    // `min` without consuming anything, so it ends the loop rather than
+    // recursing forever.
+    if max.is_none_or(|max| count < max)
+        && single(node, text, i, &mut |j| match j == i {
+            true => sequence(rest, text, j, k),
+            false => repeat(node, min, max, count + 1, rest, text, j, k),
+        })
+    {
+        return true;
+    }
+    count >= min && sequence(rest, text, i, k)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.pos += c.is_some() as usize;
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        self.pos += found as usize;
+        found
+    }
+
+    fn alternation(&mut self) -> Result<Vec<Vec<Node>>, Error> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.eat('|') {
+            alternatives.push(self.sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Node>, Error> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        match self.next() {
+            Some('(') => {
+                if self.eat('?') && !self.eat(':') {
+                    return Err(Error("unsupported group syntax".into()));
+                }
+                let group = self.alternation()?;
+                if !self.eat(')') {
+                    return Err(Error("unclosed group".into()));
+                }
+                Ok(Node::Group(group))
+            }
+            Some('[') => self.class(),
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('\\') => self.escape().map(|class| match class {
+                Ok(class) => Node::Class(class),
+                Err(c) => Node::Char(c),
+            }),
+            Some('*' | '+' | '?' | '{') => Err(Error("nothing to repeat".into())),
+            Some(c) => Ok(Node::Char(c)),
+            None => unreachable!("atom called at end of pattern"),
+        }
+    }
+
+    /// Parses the character after a backslash into either a shorthand class
+    /// or an escaped literal.
+    fn escape(&mut self) -> Result<Result<Class, char>, Error> {
+        let c = self
+            .next()
+            .ok_or_else(|| Error("trailing backslash".into()))?;
+        let (negated, ranges) = match c {
+            'd' | 'D' => (c == 'D', vec![('0', '9')]),
+            'w' | 'W' => (
+                c == 'W',
+                vec![('0', '9'), ('A', 'Z'), ('a', 'z'), ('_', '_')],
+            ),
+            's' | 'S' => (c == 'S', vec![(' ', ' '), ('\t', '\r')]),
+            _ => return Ok(Err(c)),
+        };
+        Ok(Ok(Class { negated, ranges }))
+    }
+
+    fn class(&mut self) -> Result<Node, Error> {
+        let negated = self.eat('^');
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let lo = match self.next() {
+                None => return Err(Error("unclosed character class".into())),
+                Some(']') if !first => break,
+                Some('\\') => match self.escape()? {
+                    Ok(class) if !class.negated => {
+                        ranges.extend(class.ranges);
+                        first = false;
+                        continue;
+                    }
+                    Ok(_) => return Err(Error("negated shorthand inside a class".into())),
+                    Err(c) => c,
+                },
+                Some(c) => c,
+            };
+            first = false;
+            let hi = if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                self.pos += 1;
+                match self.next() {
+                    None => return Err(Error("unclosed character class".into())),
+                    Some('\\') => match self.escape()? {
+                        Err(c) => c,
+                        Ok(_) => return Err(Error("invalid class range".into())),
+                    },
+                    Some(c) => c,
+                }
+            } else {
+                lo
+            };
+            if hi < lo {
+                return Err(Error(format!("invalid class range {lo}-{hi}")));
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Node::Class(Class { negated, ranges }))
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, Error> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => return self.counted(atom),
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        self.repeated(atom, min, max)
+    }
+
+    fn counted(&mut self, atom: Node) -> Result<Node, Error> {
+        let start = self.pos + 1;
+        let Some(len) = self.chars[start..].iter().position(|&c| c == '}') else {
+            return Err(Error("unclosed repetition".into()));
+        };
+        let spec: String = self.chars[start..start + len].iter().collect();
+        let invalid = || Error(format!("invalid repetition {{{spec}}}"));
+        let (min, max) = match spec.split_once(',') {
+            None => {
+                let n = spec.parse().map_err(|_| invalid())?;
+                (n, Some(n))
+            }
+            Some((min, "")) => (min.parse().map_err(|_| invalid())?, None),
+            Some((min, max)) => (
+                min.parse().map_err(|_| invalid())?,
+                Some(max.parse().map_err(|_| invalid())?),
+            ),
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(invalid());
+        }
+        self.pos = start + len + 1;
+        self.repeated(atom, min, max)
+    }
+
+    fn repeated(&mut self, atom: Node, min: usize, max: Option<usize>) -> Result<Node, Error> {
+        if matches!(atom, Node::Repeat { .. } | Node::Start | Node::End)
+            || matches!(self.peek(), Some('*' | '+' | '?' | '{'))
+        {
+            return Err(Error("nothing to repeat".into()));
+        }
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches() {
+        let cases = [
+            ("abc", "xabcx", true),
+            ("abc", "abd", false),
+            ("a.c", "abc", true),
+            ("a.c", "ac", false),
+            // Classes.
+            ("^[0-9A-F]+$", "0A1F", true),
+            ("^[0-9A-F]+$", "0a1f", false),
+            ("^[^0]", "10", true),
+            ("^[^0]", "01", false),
+            ("[-a]", "-", true),
+            ("[a-]", "-", true),
+            ("[]a]", "]", true),
+            ("^\\d+$", "123", true),
+            ("^\\d+$", "12a", false),
+            ("^\\D$", "a", true),
+            ("^\\w+$", "a_1", true),
+            ("\\W", "abc", false),
+            ("\\s", "a b", true),
+            ("^\\S+$", "a\tb", false),
+            ("[\\d_]", "_", true),
+            // Escapes.
+            ("a\\.c", "a.c", true),
+            ("a\\.c", "abc", false),
+            ("\\(\\)", "()", true),
+            ("\\\\", "\\", true),
+            ("[\\]]", "]", true),
+            ("[a\\-z]", "b", false),
+            // Anchors.
+            ("^12", "123", true),
+            ("^23", "123", false),
+            ("23$", "123", true),
+            ("12$", "123", false),
+            ("^$", "", true),
+            ("^$", "a", false),
+            // Alternation.
+            ("^(12|34)$", "34", true),
+            ("^(12|34)$", "1234", false),
+            ("cat|dog", "hotdog", true),
+            ("^a|b$", "xb", true),
+            ("^(?:a|b)c", "bc", true),
+            // Repetition.
+            ("^ab*c$", "ac", true),
+            ("^ab+c$", "ac", false),
+            ("^ab?c$", "abbc", false),
+            ("^a*a$", "aaa", true),
+            ("^a{3}$", "aaa", true),
+            ("^a{3}$", "aa", false),
+            ("^a{2,}$", "aaaa", true),
+            ("^a{2,}$", "a", false),
+            ("^a{1,2}$", "aaa", false),
+            ("^a{0,2}b$", "b", true),
+            ("^(ab){2}$", "abab", true),
+            ("^[0-9A-F]{2}(00)*$", "1F0000", true),
+            // Empty repetitions count toward the minimum.
+            ("^(|a)+$", "", true),
+            ("^(|a)+$", "aa", true),
+            ("^(a?){3}$", "a", true),
+            ("^(a*){2,}$", "", true),
+            ("^(a*)+b$", "aaab", true),
+        ];
+        for (pattern, text, expected) in cases {
+            let re = Regex::new(pattern).unwrap();
+            assert_eq!(re.is_match(text), expected, "{pattern:?} on {text:?}");
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        let cases = [
+            ("(ab", "unclosed group"),
+            ("ab)", "unmatched `)`"),
+            ("(?=a)", "unsupported group syntax"),
+            ("[ab", "unclosed character class"),
+            ("[z-a]", "invalid class range z-a"),
+            ("[\\D]", "negated shorthand inside a class"),
+            ("[a-\\d]", "invalid class range"),
+            ("a\\", "trailing backslash"),
+            ("*a", "nothing to repeat"),
+            ("a**", "nothing to repeat"),
+            ("^*", "nothing to repeat"),
+            ("a{2", "unclosed repetition"),
+            ("a{x}", "invalid repetition {x}"),
+            ("a{3,1}", "invalid repetition {3,1}"),
+        ];
+        for (pattern, message) in cases {
+            match Regex::new(pattern) {
+                Ok(_) => panic!("{pattern:?} parsed"),
+                Err(e) => assert_eq!(e.to_string(), message, "{pattern:?}"),
+            }
+        }
+    }
+}
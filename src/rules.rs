@@ -0,0 +1,76 @@
+//! Row coloring rules loaded with `--rules`. The file is a JSON list of
+//! rules checked in order against each ID's latest payload; the first one
+//! that matches colors the row:
+//!
+//! ```json
+//! [
+//!   { "id": "123", "byte": 2, "range": [129, 255], "color": "red" },
+//!   { "byte": 0, "eq": 3, "color": "yellow" },
+//!   { "id": "7E8", "byte": 1, "mask": 128, "color": "#ff8000" }
+//! ]
+//! ```
+//!
+//! A rule without an `id` applies to every ID. `eq`, `range` (inclusive)
+//! and `mask` (all of its bits set) may be combined; all given conditions
+//! must hold, and a payload too short to have `byte` never matches.
+
+use crate::canbus;
+use anyhow::{Context, Result};
+use ratatui::style::Color;
+use serde::Deserialize;
+use serde_with::{serde_as, DisplayFromStr};
+use std::{fs, path::Path};
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    id: Option<String>,
+    byte: usize,
+    eq: Option<u8>,
+    range: Option<(u8, u8)>,
+    mask: Option<u8>,
+    #[serde_as(as = "DisplayFromStr")]
+    color: Color,
+}
+
+impl Rule {
+    fn matches(&self, id: &str, data: &[u8]) -> bool {
+        let Some(&byte) = data.get(self.byte) else {
+            return false;
+        };
+        self.id.as_deref().is_none_or(|rule| same_id(rule, id))
+            && self.eq.is_none_or(|eq| byte == eq)
+            && self.range.is_none_or(|(lo, hi)| lo <= byte && byte <= hi)
+            && self.mask.is_none_or(|mask| byte & mask == mask)
+    }
+}
+
+#[derive(Default)]
+pub struct Rules(Vec<Rule>);
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("cannot read rules file {}", path.display()))?;
+        serde_json::from_str(&json)
+            .map(Self)
+            .with_context(|| format!("cannot parse rules file {}", path.display()))
+    }
+
+    /// Color of the first rule matching `id` and its latest payload.
+    pub fn color(&self, id: &str, data: &[u8]) -> Option<Color> {
+        self.0
+            .iter()
+            .find(|rule| rule.matches(id, data))
+            .map(|rule| rule.color)
+    }
+}
+
+/// Compares IDs by value so `0x123`, `123` and `0123` are the same ID.
+fn same_id(a: &str, b: &str) -> bool {
+    match (canbus::parse_id(a), canbus::parse_id(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.eq_ignore_ascii_case(b),
+    }
+}
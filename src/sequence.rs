@@ -0,0 +1,50 @@
+//! Dropped-frame detection from a rolling sequence counter in one byte.
+
+/// A payload byte that counts up by one every frame and wraps around.
+/// The counter is taken to wrap after the largest value it has shown,
+/// e.g. at 16 for a nibble counter or at 15 for one that runs 0-14.
+#[derive(Clone)]
+pub struct Sequence {
+    pub byte: usize,
+    /// Counter values skipped so far, each a frame that likely went
+    /// missing.
+    pub dropped: u64,
+    modulus: u16,
+    last: Option<u8>,
+}
+
+impl Sequence {
+    /// Starts counting with the frames already stored for the ID.
+    pub fn new<'a>(byte: usize, history: impl Iterator<Item = &'a [u8]> + Clone) -> Self {
+        let max = history
+            .clone()
+            .filter_map(|data| data.get(byte))
+            .max()
+            .copied()
+            .unwrap_or_default();
+        let mut sequence = Self {
+            byte,
+            dropped: 0,
+            modulus: u16::from(max) + 1,
+            last: None,
+        };
+        for data in history {
+            sequence.feed(data);
+        }
+        sequence
+    }
+
+    /// Checks the next frame's counter against the previous one. A counter
+    /// that didn't move is a repeat, not a gap.
+    pub fn feed(&mut self, data: &[u8]) {
+        let Some(&value) = data.get(self.byte) else {
+            return;
+        };
+        self.modulus = self.modulus.max(u16::from(value) + 1);
+        if let Some(last) = self.last {
+            let step = (u16::from(value) + self.modulus - u16::from(last)) % self.modulus;
+            self.dropped += u64::from(step.saturating_sub(1));
+        }
+        self.last = Some(value);
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{bail, Error};
+use std::{fs::File, io, path::Path, str::FromStr};
+
+#[derive(Clone, Copy)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Clone, Copy)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    pub baud: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            baud: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+impl FromStr for Parity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "odd" => Ok(Self::Odd),
+            "even" => Ok(Self::Even),
+            _ => bail!("invalid parity `{s}` (expected none, odd or even)"),
+        }
+    }
+}
+
+impl FromStr for StopBits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" => Ok(Self::One),
+            "2" => Ok(Self::Two),
+            _ => bail!("invalid stop bits `{s}` (expected 1 or 2)"),
+        }
+    }
+}
+
+/// Opens `path` and, if it is a terminal device, puts it into raw mode with
+/// the requested line settings. Plain files are returned untouched so
+/// captures can be read through the same path.
+pub fn open(path: &Path, settings: &Settings) -> io::Result<File> {
+    let file = File::open(path)?;
+    configure(&file, settings)?;
+    Ok(file)
+}
+
+#[cfg(unix)]
+fn configure(file: &File, settings: &Settings) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    if unsafe { libc::isatty(fd) } == 0 {
+        return Ok(());
+    }
+
+    let speed = match settings.baud {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        #[cfg(target_os = "linux")]
+        460800 => libc::B460800,
+        #[cfg(target_os = "linux")]
+        500000 => libc::B500000,
+        #[cfg(target_os = "linux")]
+        921600 => libc::B921600,
+        #[cfg(target_os = "linux")]
+        1000000 => libc::B1000000,
+        baud => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported baud rate {baud}"),
+            ))
+        }
+    };
+    let size = match settings.data_bits {
+        5 => libc::CS5,
+        6 => libc::CS6,
+        7 => libc::CS7,
+        8 => libc::CS8,
+        bits => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported data bits {bits}"),
+            ))
+        }
+    };
+
+    let mut tio = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut tio) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    unsafe { libc::cfmakeraw(&mut tio) };
+    tio.c_cflag &= !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB);
+    tio.c_cflag |= size | libc::CLOCAL | libc::CREAD;
+    match settings.parity {
+        Parity::None => {}
+        Parity::Odd => tio.c_cflag |= libc::PARENB | libc::PARODD,
+        Parity::Even => tio.c_cflag |= libc::PARENB,
+    }
+    if let StopBits::Two = settings.stop_bits {
+        tio.c_cflag |= libc::CSTOPB;
+    }
+    tio.c_cc[libc::VMIN] = 1;
+    tio.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::cfsetspeed(&mut tio, speed) } != 0
+        || unsafe { libc::tcsetattr(fd, libc::TCSANOW, &tio) } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn configure(_file: &File, _settings: &Settings) -> io::Result<()> {
+    Ok(())
+}
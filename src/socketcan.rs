@@ -0,0 +1,205 @@
+use crate::canbus::{self, Event, FrameKind, Message, Source, Transmit};
+use std::{
+    ffi::CString,
+    io,
+    mem::{size_of, size_of_val, zeroed},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::mpsc::Receiver,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+struct Socket(OwnedFd);
+
+impl Socket {
+    fn open(interface: &str) -> io::Result<Self> {
+        let name =
+            CString::new(interface).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = unsafe { libc::socket(libc::PF_CAN, libc::SOCK_RAW, libc::CAN_RAW) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = Self(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let mut addr = unsafe { zeroed::<libc::sockaddr_can>() };
+        addr.can_family = libc::AF_CAN as libc::sa_family_t;
+        addr.can_ifindex = index as libc::c_int;
+        let on: libc::c_int = 1;
+        unsafe {
+            if libc::bind(
+                fd,
+                &addr as *const _ as *const libc::sockaddr,
+                size_of_val(&addr) as libc::socklen_t,
+            ) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            // Best effort: without kernel timestamps we fall back to now().
+            libc::setsockopt(
+                fd,
+                libc::SOL_SOCKET,
+                libc::SO_TIMESTAMP,
+                &on as *const _ as *const libc::c_void,
+                size_of_val(&on) as libc::socklen_t,
+            );
+        }
+        Ok(socket)
+    }
+
+    fn recv(&self) -> io::Result<(libc::can_frame, Option<SystemTime>)> {
+        let mut frame = unsafe { zeroed::<libc::can_frame>() };
+        let mut iov = libc::iovec {
+            iov_base: &mut frame as *mut _ as *mut libc::c_void,
+            iov_len: size_of::<libc::can_frame>(),
+        };
+        let mut control = [0u64; 8];
+        let mut msg = unsafe { zeroed::<libc::msghdr>() };
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = size_of_val(&control) as _;
+
+        if unsafe { libc::recvmsg(self.0.as_raw_fd(), &mut msg, 0) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut ts = None;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET
+                    && (*cmsg).cmsg_type == libc::SCM_TIMESTAMP
+                {
+                    let tv = (libc::CMSG_DATA(cmsg) as *const libc::timeval).read_unaligned();
+                    ts = Some(
+                        UNIX_EPOCH + Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000),
+                    );
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+        Ok((frame, ts))
+    }
+
+    fn send(&self, frame: &libc::can_frame) -> io::Result<()> {
+        let len = size_of_val(frame);
+        let written = unsafe {
+            libc::write(
+                self.0.as_raw_fd(),
+                frame as *const _ as *const libc::c_void,
+                len,
+            )
+        };
+        match written {
+            n if n < 0 => Err(io::Error::last_os_error()),
+            n if n as usize != len => Err(io::Error::other("short write to SocketCAN")),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<&Message> for libc::can_frame {
+    fn from(msg: &Message) -> Self {
+        let mut frame = unsafe { zeroed::<libc::can_frame>() };
+        let id = canbus::parse_id(&msg.id).unwrap_or_default();
+        frame.can_id = if msg.is_extended {
+            id & libc::CAN_EFF_MASK | libc::CAN_EFF_FLAG
+        } else {
+            id & libc::CAN_SFF_MASK
+        };
+        if msg.kind == FrameKind::Remote {
+            frame.can_id |= libc::CAN_RTR_FLAG;
+        }
+        frame.can_dlc = msg.dlc.min(libc::CAN_MAX_DLEN) as u8;
+        for (byte, pair) in frame.data.iter_mut().zip(msg.data.as_bytes().chunks(2)) {
+            *byte = std::str::from_utf8(pair)
+                .ok()
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .unwrap_or_default();
+        }
+        frame
+    }
+}
+
+impl From<(libc::can_frame, Option<SystemTime>)> for Message {
+    fn from((frame, ts): (libc::can_frame, Option<SystemTime>)) -> Self {
+        let is_extended = frame.can_id & libc::CAN_EFF_FLAG != 0;
+        let kind = if frame.can_id & libc::CAN_ERR_FLAG != 0 {
+            FrameKind::Error
+        } else if frame.can_id & libc::CAN_RTR_FLAG != 0 {
+            FrameKind::Remote
+        } else {
+            FrameKind::Data
+        };
+        let id = if is_extended || kind == FrameKind::Error {
+            format!("{:08X}", frame.can_id & libc::CAN_EFF_MASK)
+        } else {
+            format!("{:03X}", frame.can_id & libc::CAN_SFF_MASK)
+        };
+        let dlc = frame.can_dlc as usize;
+        let len = match kind {
+            FrameKind::Remote => 0,
+            _ => dlc.min(libc::CAN_MAX_DLEN),
+        };
+        Self {
+            id,
+            is_extended,
+            kind,
+            dlc,
+            data: frame.data[..len]
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect(),
+            ts: ts.unwrap_or_else(SystemTime::now),
+            source: String::new(),
+        }
+    }
+}
+
+/// Reads raw frames from a SocketCAN interface such as `can0`. Frames are
+/// stamped with the kernel receive time when the socket provides one, and
+/// carry the interface's name as their source.
+pub struct SocketCanSource(Socket, String);
+
+impl SocketCanSource {
+    pub fn open(interface: &str) -> io::Result<Self> {
+        Ok(Self(Socket::open(interface)?, interface.to_string()))
+    }
+}
+
+/// Sends frames through its own handle on the source's socket.
+struct SocketCanTransmit(Socket);
+
+impl Transmit for SocketCanTransmit {
+    fn transmit(&mut self, frame: &Message) -> io::Result<()> {
+        self.0.send(&frame.into())
+    }
+}
+
+impl Source for SocketCanSource {
+    fn messages(self: Box<Self>) -> Receiver<Event> {
+        canbus::spawn(move |tx| loop {
+            match self.0.recv() {
+                Ok(frame) => {
+                    let frame = Message {
+                        source: self.1.clone(),
+                        ..frame.into()
+                    };
+                    if tx.send(Event::Frame(frame)).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => return canbus::fail(&tx, format!("SocketCAN read failed: {e}")),
+            }
+        })
+    }
+
+    fn transmitter(&self) -> Option<Box<dyn Transmit>> {
+        let socket = self.0 .0.try_clone().ok()?;
+        Some(Box::new(SocketCanTransmit(Socket(socket))))
+    }
+}
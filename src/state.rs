@@ -0,0 +1,152 @@
+use crate::canbus::{self, IdPattern};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Per-ID choices and view settings that survive restarts. IDs are kept
+/// as [`canbus::key_id`] writes them, however they were typed or received.
+#[serde_as]
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct State {
+    pub ignored: BTreeSet<String>,
+    pub pinned: BTreeSet<String>,
+    /// IDs matching any of these start out ignored.
+    #[serde_as(as = "Vec<DisplayFromStr>")]
+    pub ignore_patterns: Vec<IdPattern>,
+    /// Notes written on IDs, by ID.
+    pub notes: BTreeMap<String, String>,
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// Column toggles, display mode and sort order of the last session.
+    pub view: Option<crate::View>,
+    /// The theme of the last session, unless `--theme` overrides it.
+    pub theme: Option<String>,
+}
+
+impl State {
+    /// Loads the state file, treating a missing file as empty state.
+    pub fn load(path: &Path) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map(Self::rekeyed)
+                .with_context(|| format!("cannot parse state file {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("cannot read state file {}", path.display())),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Rewrites IDs saved in other forms, e.g. by older versions.
+    fn rekeyed(self) -> Self {
+        Self {
+            ignored: self.ignored.iter().map(|id| canbus::key_id(id)).collect(),
+            pinned: self.pinned.iter().map(|id| canbus::key_id(id)).collect(),
+            notes: self
+                .notes
+                .into_iter()
+                .map(|(id, note)| (canbus::key_id(&id), note))
+                .collect(),
+            tags: self
+                .tags
+                .into_iter()
+                .map(|(id, tags)| (canbus::key_id(&id), tags))
+                .collect(),
+            ..self
+        }
+    }
+
+    pub fn ignores(&self, id: &str) -> bool {
+        self.ignored.contains(&canbus::key_id(id)) || self.matches_pattern(id)
+    }
+
+    pub fn pins(&self, id: &str) -> bool {
+        self.pinned.contains(&canbus::key_id(id))
+    }
+
+    pub fn note(&self, id: &str) -> Option<&String> {
+        self.notes.get(&canbus::key_id(id))
+    }
+
+    pub fn tags(&self, id: &str) -> &[String] {
+        self.tags
+            .get(&canbus::key_id(id))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn matches_pattern(&self, id: &str) -> bool {
+        self.ignore_patterns
+            .iter()
+            .any(|pattern| pattern.matches(id))
+    }
+
+    /// Records an ID's choices, note and tags. An ID that an ignore pattern
+    /// covers isn't added to the ignored IDs, so it follows the pattern if
+    /// that goes.
+    pub fn update(
+        &mut self,
+        id: &str,
+        ignored: bool,
+        pinned: bool,
+        note: Option<&str>,
+        tags: &[String],
+    ) {
+        let ignored = ignored && !self.matches_pattern(id);
+        let id = &canbus::key_id(id);
+        for (set, on) in [(&mut self.ignored, ignored), (&mut self.pinned, pinned)] {
+            if on {
+                set.insert(id.to_string());
+            } else {
+                set.remove(id);
+            }
+        }
+        match note {
+            Some(note) => self.notes.insert(id.to_string(), note.to_string()),
+            None => self.notes.remove(id),
+        };
+        match tags.is_empty() {
+            true => self.tags.remove(id),
+            false => self.tags.insert(id.to_string(), tags.to_vec()),
+        };
+    }
+}
+
+/// `$XDG_CONFIG_HOME/canalyzer/state.json`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let config = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("canalyzer").join("state.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_keyed_by_value() {
+        let mut state = State::default();
+        state.update("07e8", true, true, Some("engine"), &["obd".to_string()]);
+        assert!(state.ignores("7E8") && state.pins("0x7e8"));
+        assert_eq!(state.note("7e8").map(String::as_str), Some("engine"));
+        assert_eq!(state.tags("007E8"), ["obd"]);
+        state.update("7E8", false, false, None, &[]);
+        assert!(state.ignored.is_empty() && state.pinned.is_empty() && state.notes.is_empty());
+
+        let saved: State = serde_json::from_str(r#"{"pinned": ["07e8"]}"#).unwrap();
+        assert!(saved.rekeyed().pins("7E8"));
+    }
+}
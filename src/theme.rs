@@ -0,0 +1,240 @@
+use anyhow::{anyhow, Error};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use std::{io::IsTerminal, str::FromStr, time::Duration};
+
+/// Background colors that fade out as a frame ages: a frame younger than a
+/// step's threshold gets that step's color, and older frames get none.
+/// Written as `<secs>=<color>` pairs, e.g. `0.5=#ff9b35,1=red`.
+#[derive(Clone)]
+pub struct Fade(Vec<(Duration, Color)>);
+
+impl Default for Fade {
+    fn default() -> Self {
+        Self(vec![
+            (Duration::from_secs(1), Color::Rgb(255, 155, 53)),
+            (Duration::from_secs(2), Color::Rgb(189, 55, 10)),
+            (Duration::from_secs(3), Color::Rgb(94, 0, 0)),
+        ])
+    }
+}
+
+impl Fade {
+    pub fn color(&self, age: Duration) -> Option<Color> {
+        self.0
+            .iter()
+            .find(|&&(threshold, _)| age < threshold)
+            .map(|&(_, color)| color)
+    }
+}
+
+impl FromStr for Fade {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = s
+            .split(',')
+            .map(|step| {
+                let (secs, color) = step
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("expected <secs>=<color>, got `{step}`"))?;
+                let threshold = Duration::try_from_secs_f64(secs.trim().parse()?)?;
+                let color = color
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("invalid color `{color}`"))?;
+                Ok((threshold, color))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        steps.sort_by_key(|&(threshold, _)| threshold);
+        Ok(Self(steps))
+    }
+}
+
+/// Every style the UI uses, so a preset can restyle all of it at once.
+#[derive(Clone)]
+pub struct Theme {
+    /// The preset's name, as `--theme` takes it.
+    pub name: &'static str,
+    pub fade: Fade,
+    /// Cell background once a frame has faded out.
+    pub background: Color,
+    pub text: Style,
+    /// Bytes and DLCs that differ from the previous frame.
+    pub changed: Style,
+    /// Changed bytes that went up or down, with `--diff-direction`.
+    pub increased: Style,
+    pub decreased: Style,
+    /// Bytes that are the same in every stored frame.
+    pub constant: Style,
+    /// The RTR and STALE markers.
+    pub badge: Style,
+    /// Rows of ignored IDs and of error frames.
+    pub ignored: Style,
+    pub error_frame: Style,
+    /// Key names in the help and signal names in the detail pane.
+    pub key: Style,
+    pub title: Style,
+    /// The title bar while a trigger has just fired.
+    pub flash: Style,
+    pub selection: Style,
+    /// The byte under the cursor in the detail panes.
+    pub cursor: Style,
+    /// Group headers and column names.
+    pub header: Style,
+    /// Errors and notices at the right of the title bar.
+    pub error: Style,
+    pub notice: Style,
+    /// Whether `--rules` may color rows.
+    pub rule_colors: bool,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    pub fn dark() -> Self {
+        Self {
+            name: "dark",
+            fade: Fade::default(),
+            background: Color::Black,
+            text: Style::new().white(),
+            changed: Style::new().light_cyan(),
+            increased: Style::new().light_green(),
+            decreased: Style::new().light_red(),
+            constant: Style::new().dark_gray(),
+            badge: Style::new().yellow().bold(),
+            ignored: Style::new().dark_gray().crossed_out(),
+            error_frame: Style::new().red(),
+            key: Style::new().yellow(),
+            title: Style::new().yellow().on_blue(),
+            flash: Style::new().black().on_yellow(),
+            selection: Style::new(),
+            cursor: Style::new().reversed(),
+            header: Style::new().bold(),
+            error: Style::new().white().on_red(),
+            notice: Style::new().black().on_green(),
+            rule_colors: true,
+        }
+    }
+
+    pub fn light() -> Self {
+        Self {
+            name: "light",
+            fade: Fade(vec![
+                (Duration::from_secs(1), Color::Rgb(255, 196, 120)),
+                (Duration::from_secs(2), Color::Rgb(255, 222, 176)),
+                (Duration::from_secs(3), Color::Rgb(255, 240, 222)),
+            ]),
+            background: Color::Reset,
+            text: Style::new().black(),
+            changed: Style::new().blue(),
+            increased: Style::new().green(),
+            decreased: Style::new().red(),
+            constant: Style::new().gray(),
+            badge: Style::new().magenta().bold(),
+            ignored: Style::new().dark_gray().crossed_out(),
+            error_frame: Style::new().red(),
+            key: Style::new().magenta(),
+            title: Style::new().white().on_blue(),
+            flash: Style::new().black().on_light_yellow(),
+            selection: Style::new().on_gray(),
+            cursor: Style::new().reversed(),
+            header: Style::new().bold(),
+            error: Style::new().white().on_red(),
+            notice: Style::new().black().on_green(),
+            rule_colors: true,
+        }
+    }
+
+    /// Plain ANSI colors only, with bold changes and a reversed selection.
+    pub fn high_contrast() -> Self {
+        Self {
+            name: "high-contrast",
+            fade: Fade(vec![
+                (Duration::from_secs(1), Color::Red),
+                (Duration::from_secs(2), Color::Blue),
+                (Duration::from_secs(3), Color::DarkGray),
+            ]),
+            background: Color::Black,
+            text: Style::new().white(),
+            changed: Style::new().light_yellow().bold(),
+            increased: Style::new().light_green().bold(),
+            decreased: Style::new().light_red().bold(),
+            constant: Style::new().gray(),
+            badge: Style::new().light_green().bold(),
+            ignored: Style::new().dark_gray().crossed_out(),
+            error_frame: Style::new().light_red(),
+            key: Style::new().light_yellow(),
+            title: Style::new().black().on_white(),
+            flash: Style::new().white().on_red(),
+            selection: Style::new().add_modifier(Modifier::REVERSED),
+            cursor: Style::new().reversed(),
+            header: Style::new().bold(),
+            error: Style::new().white().on_red(),
+            notice: Style::new().black().on_green(),
+            rule_colors: true,
+        }
+    }
+
+    /// No colors at all, only attributes: changes are bold, rising bytes
+    /// also underlined and falling ones only underlined. Used on its own
+    /// under `NO_COLOR` or when stdout isn't a terminal.
+    pub fn monochrome() -> Self {
+        Self {
+            name: "monochrome",
+            fade: Fade(Vec::new()),
+            background: Color::Reset,
+            text: Style::new(),
+            changed: Style::new().bold(),
+            increased: Style::new().bold().underlined(),
+            decreased: Style::new().underlined(),
+            constant: Style::new().dim(),
+            badge: Style::new().bold(),
+            ignored: Style::new().dim().crossed_out(),
+            error_frame: Style::new().bold(),
+            key: Style::new().bold(),
+            title: Style::new().reversed(),
+            flash: Style::new().bold(),
+            selection: Style::new().reversed(),
+            cursor: Style::new().reversed(),
+            header: Style::new().bold(),
+            error: Style::new().reversed().bold(),
+            notice: Style::new().reversed(),
+            rule_colors: false,
+        }
+    }
+
+    /// The style of a row that `--rules` colors.
+    pub fn rule(&self, color: Color) -> Style {
+        match self.rule_colors {
+            true => Style::new().fg(color),
+            false => Style::new(),
+        }
+    }
+}
+
+/// Whether to stay away from colors: `NO_COLOR` is set to anything but an
+/// empty string, or stdout isn't a terminal.
+pub fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+        || !std::io::stdout().is_terminal()
+}
+
+impl FromStr for Theme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dark" => Ok(Self::dark()),
+            "light" => Ok(Self::light()),
+            "high-contrast" => Ok(Self::high_contrast()),
+            "monochrome" => Ok(Self::monochrome()),
+            _ => Err(anyhow!(
+                "unknown theme `{s}`, expected dark, light, high-contrast or monochrome"
+            )),
+        }
+    }
+}
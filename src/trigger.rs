@@ -0,0 +1,64 @@
+use crate::canbus;
+use anyhow::{anyhow, Error};
+use std::{fmt, str::FromStr};
+
+/// Fires when a frame for `id` arrives whose payload starts with `prefix`.
+/// Written as `<id>#<prefix>`, e.g. `7E8#037F`.
+#[derive(Clone, PartialEq)]
+pub struct Trigger {
+    id: String,
+    prefix: String,
+}
+
+impl Trigger {
+    pub fn new(id: &str, prefix: &str) -> Self {
+        Self {
+            id: canbus::key_id(id),
+            prefix: prefix
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .collect::<String>()
+                .to_ascii_uppercase(),
+        }
+    }
+
+    pub fn matches(&self, id: &str, data: &str) -> bool {
+        let id = match (canbus::parse_id(id), canbus::parse_id(&self.id)) {
+            (Some(id), Some(armed)) => id == armed,
+            _ => id.eq_ignore_ascii_case(&self.id),
+        };
+        id && data.to_ascii_uppercase().starts_with(&self.prefix)
+    }
+}
+
+impl FromStr for Trigger {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (id, prefix) = s
+            .split_once('#')
+            .ok_or_else(|| anyhow!("expected <id>#<data>, got `{s}`"))?;
+        Ok(Self::new(id, prefix))
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}", self.id, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_match_by_value() {
+        let trigger: Trigger = "07E8#037F".parse().unwrap();
+        assert!(trigger.matches("7E8", "037F12"));
+        assert!(trigger.matches("0x7e8", "037f"));
+        assert!(!trigger.matches("7E9", "037F"));
+        assert!(trigger == Trigger::new("7E8", "03 7F"));
+        assert_eq!(trigger.to_string(), "7E8#037F");
+    }
+}
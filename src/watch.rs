@@ -0,0 +1,71 @@
+//! A manual decoder: a run of payload bytes read as one integer, for IDs
+//! without a DBC definition.
+
+use std::fmt;
+
+/// The bytes from the chosen one on, read as an integer.
+#[derive(Clone, Copy)]
+pub struct Watch {
+    /// Bytes in the integer, 1 to 8.
+    pub len: usize,
+    pub big_endian: bool,
+    pub signed: bool,
+}
+
+impl Default for Watch {
+    fn default() -> Self {
+        Self {
+            len: 1,
+            big_endian: false,
+            signed: false,
+        }
+    }
+}
+
+impl Watch {
+    /// The bytes at `start` as an unsigned integer in the byte order, or
+    /// `None` if the payload is too short.
+    pub fn raw(self, data: &[u8], start: usize) -> Option<u64> {
+        let bytes = data.get(start..start + self.len)?;
+        let fold = |raw: u64, &b: &u8| (raw << 8) | u64::from(b);
+        Some(match self.big_endian {
+            true => bytes.iter().fold(0, fold),
+            false => bytes.iter().rev().fold(0, fold),
+        })
+    }
+
+    /// `raw` as the integer it stands for, two's complement when signed.
+    pub fn value(self, raw: u64) -> i128 {
+        let bits = 8 * self.len as u32;
+        match self.signed && (raw >> (bits - 1)) & 1 == 1 {
+            true => i128::from(raw) - (1 << bits),
+            false => i128::from(raw),
+        }
+    }
+
+    /// `raw` shifted so that the most negative value plots lowest.
+    pub fn plotted(self, raw: u64) -> u64 {
+        match self.signed {
+            true => raw ^ (1 << (8 * self.len - 1)),
+            false => raw,
+        }
+    }
+
+    /// The largest plotted value.
+    pub fn max(self) -> u64 {
+        u64::MAX >> (64 - 8 * self.len)
+    }
+}
+
+/// The integer's type, e.g. `u16 big-endian`; one byte has no byte order.
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.signed { 'i' } else { 'u' };
+        write!(f, "{sign}{}", 8 * self.len)?;
+        match (self.len, self.big_endian) {
+            (1, _) => Ok(()),
+            (_, true) => f.write_str(" big-endian"),
+            (_, false) => f.write_str(" little-endian"),
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! A broadcast-only WebSocket server (RFC 6455) for browser dashboards.
+//! Clients are sent every frame as a JSON text message and anything they
+//! send is ignored.
+
+use crate::{canbus, export, Message};
+use anyhow::{Context, Result};
+use base64::Engine;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{sync_channel, SyncSender},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Appended to a client's key to form the accept hash.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// Frames queued for broadcasting; more are dropped rather than stalling
+/// the UI.
+const QUEUE: usize = 4096;
+/// A client that can't take a frame within this long is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Accepts clients on one thread and fans frames out to them from another,
+/// in the same JSON as `--stream` prints.
+pub struct Server {
+    tx: SyncSender<canbus::Message>,
+}
+
+impl Server {
+    pub fn serve(addr: SocketAddr) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("cannot listen on {addr}"))?;
+        let clients = Arc::new(Mutex::new(Vec::<TcpStream>::new()));
+        let accepted = Arc::clone(&clients);
+        std::thread::spawn(move || {
+            for stream in listener.incoming().map_while(Result::ok) {
+                // Clients that fail the handshake are simply not added.
+                if let Ok(stream) = handshake(stream) {
+                    accepted.lock().unwrap().push(stream);
+                }
+            }
+        });
+        let (tx, rx) = sync_channel::<canbus::Message>(QUEUE);
+        std::thread::spawn(move || {
+            for frame in rx {
+                let msg = Message::from(frame);
+                let mut json = Vec::new();
+                if export::json_line(&mut json, &msg, &msg.values[0]).is_err() {
+                    continue;
+                }
+                json.pop();
+                let frame = text_frame(&json);
+                clients
+                    .lock()
+                    .unwrap()
+                    .retain_mut(|client| client.write_all(&frame).is_ok());
+            }
+        });
+        Ok(Self { tx })
+    }
+
+    pub fn broadcast(&self, frame: &canbus::Message) {
+        let _ = self.tx.try_send(frame.clone());
+    }
+}
+
+/// Answers the HTTP upgrade request of a new client.
+fn handshake(stream: TcpStream) -> io::Result<TcpStream> {
+    stream.set_read_timeout(Some(WRITE_TIMEOUT))?;
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? <= 2 {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let mut out = &stream;
+    let Some(key) = key else {
+        write!(
+            out,
+            "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        )?;
+        return Err(io::Error::other("not a WebSocket request"));
+    };
+    let accept =
+        base64::engine::general_purpose::STANDARD.encode(sha1(format!("{key}{GUID}").as_bytes()));
+    write!(
+        out,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {accept}\r\n\r\n"
+    )?;
+    Ok(stream)
+}
+
+/// An unmasked, unfragmented text message, as servers send them.
+fn text_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x81];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// SHA-1, which the handshake needs and nothing else does.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend((data.len() as u64 * 8).to_be_bytes());
+    for block in padded.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (chunk, word) in digest.chunks_mut(4).zip(h) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}